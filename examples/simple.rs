@@ -9,7 +9,7 @@ use rare::{
 };
 use rhachis::{
     input::{InputState, Key},
-    renderers::{Texture, Transform},
+    renderers::Transform,
     *,
 };
 
@@ -27,27 +27,26 @@ impl Game for Simple {
             fov: TAU / 4.0,
             aspect: data.get_window_size().x as f32 / data.get_window_size().y as f32,
         };
-        let material = Arc::new(Material {
-            color: Texture::from_path(data, "examples/test.png", &graphics::SamplerType::Linear)
-                .unwrap(),
-        });
+        let material = Arc::new(
+            Material::from_path(data, "examples/test.png", &graphics::SamplerType::Linear).unwrap(),
+        );
         renderer.models.push(Model::new(
             data,
             vec![
                 TextureVertex {
                     pos: [0.0, 0.0, 0.0],
                     tex_coords: [0.0, 1.0],
-                    normals: [0.0, 0.0, 1.0],
+                    ..Default::default()
                 },
                 TextureVertex {
                     pos: [1.0, 0.0, 0.0],
                     tex_coords: [1.0, 1.0],
-                    normals: [0.0, 0.0, 1.0],
+                    ..Default::default()
                 },
                 TextureVertex {
                     pos: [0.0, 1.0, 0.0],
                     tex_coords: [0.0, 0.0],
-                    normals: [0.0, 0.0, 1.0],
+                    ..Default::default()
                 },
             ],
             vec![0, 1, 2],
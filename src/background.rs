@@ -0,0 +1,316 @@
+use std::{num::NonZeroU32, path::Path, sync::Arc};
+
+use glam::{Mat3, Mat4};
+use image::{
+    error::{ParameterError, ParameterErrorKind},
+    ImageError, ImageResult, Rgb32FImage, RgbaImage,
+};
+use rhachis::GameData;
+use wgpu::{util::DeviceExt, BindGroup, Buffer, Color, RenderPass, RenderPipeline, TextureView};
+
+use crate::{camera::Camera, environment, tonemap::HDR_FORMAT};
+
+/// What is drawn behind every model, the colors are linear.
+#[derive(Clone, Debug)]
+pub enum Background {
+    Color(Color),
+    /// Blends from `bottom` looking straight down to `top` looking straight
+    /// up.
+    Gradient {
+        top: Color,
+        bottom: Color,
+    },
+    Skybox(Arc<Skybox>),
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self::Color(Color::BLACK)
+    }
+}
+
+/// A cube map drawn infinitely far away around the camera.
+#[derive(Debug)]
+pub struct Skybox {
+    view: TextureView,
+}
+
+impl Skybox {
+    /// Makes a skybox from the faces of a cube map, in the order +X, -X, +Y,
+    /// -Y, +Z, -Z. The faces have to be square and all the same size,
+    /// otherwise a dimension mismatch error is returned.
+    pub fn from_faces(data: &GameData, faces: &[RgbaImage; 6]) -> ImageResult<Self> {
+        let size = faces[0].width();
+        if !faces
+            .iter()
+            .all(|face| face.width() == size && face.height() == size)
+        {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )));
+        }
+
+        let pixels: Vec<u8> = faces
+            .iter()
+            .flat_map(|face| face.as_raw().iter().copied())
+            .collect();
+        Ok(Self {
+            view: cube_view(data, size, wgpu::TextureFormat::Rgba8UnormSrgb, &pixels),
+        })
+    }
+
+    /// Loads the faces of a cube map from six paths, see
+    /// [`Skybox::from_faces`].
+    pub fn from_face_paths<P: AsRef<Path>>(data: &GameData, paths: [P; 6]) -> ImageResult<Self> {
+        let mut faces = Vec::with_capacity(6);
+        for path in paths {
+            faces.push(image::open(path)?.into_rgba8());
+        }
+        Self::from_faces(data, &faces.try_into().unwrap())
+    }
+
+    /// Makes a skybox from an equirectangular image, usually a `.hdr` file.
+    pub fn from_equirect(data: &GameData, image: &Rgb32FImage) -> Self {
+        Self {
+            view: environment::equirect_to_cube(data, image),
+        }
+    }
+
+    /// Loads an equirectangular image, see [`Skybox::from_equirect`].
+    pub fn from_path<P: AsRef<Path>>(data: &GameData, path: P) -> ImageResult<Self> {
+        Ok(Self::from_equirect(data, &image::open(path)?.into_rgb32f()))
+    }
+}
+
+/// The pipeline that draws the [`Background`], after opaque models so it's
+/// only drawn where they didn't cover.
+pub(crate) struct BackgroundPass {
+    /// Tests against the depth of the models.
+    pipeline: RenderPipeline,
+    /// Draws without a depth buffer, used before the models.
+    flat_pipeline: RenderPipeline,
+    uniform: Buffer,
+    sampler: wgpu::Sampler,
+    /// The skybox in the bind group, a black one until the background uses
+    /// one.
+    skybox: Arc<Skybox>,
+    bind_group: BindGroup,
+}
+
+impl BackgroundPass {
+    pub fn new(data: &GameData) -> Self {
+        let device = &data.graphics.device;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("background.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("background.wgsl").into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&Self::bind_group_layout(data)],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |depth_stencil| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("background_pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "background_vertex",
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "background_fragment",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: HDR_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+            })
+        };
+
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("background_uniform"),
+            size: std::mem::size_of::<BackgroundUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("skybox_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let skybox = Arc::new(Skybox {
+            view: cube_view(data, 1, wgpu::TextureFormat::Rgba8Unorm, &[0; 24]),
+        });
+        let bind_group = Self::bind_group(data, &uniform, &skybox, &sampler);
+
+        Self {
+            // The models are drawn over the far plane the background is at
+            pipeline: pipeline(Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            })),
+            flat_pipeline: pipeline(None),
+            uniform,
+            sampler,
+            skybox,
+            bind_group,
+        }
+    }
+
+    pub fn update(&mut self, data: &GameData, background: &Background, camera: &Camera) {
+        if let Background::Skybox(skybox) = background {
+            if !Arc::ptr_eq(skybox, &self.skybox) {
+                self.skybox = skybox.clone();
+                self.bind_group =
+                    Self::bind_group(data, &self.uniform, &self.skybox, &self.sampler);
+            }
+        }
+
+        // Only the rotation of the camera changes the direction of each pixel
+        let view = Mat4::from_mat3(Mat3::from_mat4(camera.view()));
+        let color = |color: &Color| [color.r, color.g, color.b, color.a].map(|x| x as f32);
+        let (top, bottom, mode) = match background {
+            Background::Color(flat) => (color(flat), color(flat), 0),
+            Background::Gradient { top, bottom } => (color(top), color(bottom), 1),
+            Background::Skybox(_) => ([0.0; 4], [0.0; 4], 2),
+        };
+        let uniform = BackgroundUniform {
+            inverse_view_proj: (camera.proj() * view).inverse().to_cols_array_2d(),
+            top,
+            bottom,
+            mode: [mode, 0, 0, 0],
+        };
+        data.graphics
+            .queue
+            .write_buffer(&self.uniform, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    /// Draws the background, `depth` is whether the pass has a depth buffer.
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>, depth: bool) {
+        render_pass.set_pipeline(match depth {
+            true => &self.pipeline,
+            false => &self.flat_pipeline,
+        });
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn bind_group(
+        data: &GameData,
+        uniform: &Buffer,
+        skybox: &Skybox,
+        sampler: &wgpu::Sampler,
+    ) -> BindGroup {
+        data.graphics
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &Self::bind_group_layout(data),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: uniform.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&skybox.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                ],
+            })
+    }
+
+    fn bind_group_layout(data: &GameData) -> wgpu::BindGroupLayout {
+        data.graphics
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::Cube,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            })
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BackgroundUniform {
+    inverse_view_proj: [[f32; 4]; 4],
+    top: [f32; 4],
+    bottom: [f32; 4],
+    mode: [u32; 4],
+}
+
+/// Uploads the six faces of a cube map, one after another in `pixels`.
+fn cube_view(
+    data: &GameData,
+    size: u32,
+    format: wgpu::TextureFormat,
+    pixels: &[u8],
+) -> TextureView {
+    data.graphics
+        .device
+        .create_texture_with_data(
+            &data.graphics.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("skybox"),
+                size: wgpu::Extent3d {
+                    width: size.max(1),
+                    height: size.max(1),
+                    depth_or_array_layers: 6,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            },
+            pixels,
+        )
+        .create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            array_layer_count: NonZeroU32::new(6),
+            ..Default::default()
+        })
+}
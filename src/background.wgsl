@@ -0,0 +1,48 @@
+struct BackgroundUniform {
+    // Turns screen positions into directions from the camera
+    inverse_view_proj: mat4x4<f32>,
+    top: vec4<f32>,
+    bottom: vec4<f32>,
+    // 0 for a color, 1 for a gradient and 2 for a skybox
+    mode: vec4<u32>,
+}
+
+@group(0)@binding(0)
+var<uniform> background: BackgroundUniform;
+@group(0)@binding(1)
+var skybox: texture_cube<f32>;
+@group(0)@binding(2)
+var skybox_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) screen: vec2<f32>,
+}
+
+// Covers the screen with a single triangle at the far plane, so only what
+// nothing was drawn over passes the depth test
+@vertex
+fn background_vertex(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var output: VertexOutput;
+    output.screen = uv * 2.0 - 1.0;
+    output.pos = vec4<f32>(output.screen, 1.0, 1.0);
+    return output;
+}
+
+@fragment
+fn background_fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let ray = background.inverse_view_proj * vec4<f32>(in.screen, 0.5, 1.0);
+    let dir = normalize(ray.xyz / ray.w);
+    switch background.mode.x {
+        case 1u: {
+            return mix(background.bottom, background.top, dir.y * 0.5 + 0.5);
+        }
+        case 2u: {
+            return vec4<f32>(textureSample(skybox, skybox_sampler, dir).rgb, 1.0);
+        }
+        default: {
+            return background.top;
+        }
+    }
+}
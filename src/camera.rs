@@ -1,6 +1,10 @@
 use glam::{Mat4, Vec3};
+use gltf::camera::Projection;
 use rhachis::{graphics::BufferCompatible, GameData};
 
+/// The distance to the near plane of every camera.
+pub const NEAR: f32 = 0.1;
+
 #[derive(Clone, Copy, Debug, Default)]
 pub struct Camera {
     pub pos: Vec3,
@@ -10,6 +14,39 @@ pub struct Camera {
 }
 
 impl Camera {
+    /// Converts a glTF camera attached to a node with the world space
+    /// `transform`, orthographic cameras aren't supported and return `None`.
+    pub fn from_gltf(camera: &gltf::Camera, transform: Mat4) -> Option<Self> {
+        match camera.projection() {
+            Projection::Perspective(perspective) => Some(Self {
+                pos: transform.transform_point3(Vec3::ZERO),
+                ty: CameraType::LookTo(transform.transform_vector3(Vec3::NEG_Z).normalize()),
+                fov: perspective.yfov(),
+                aspect: perspective.aspect_ratio().unwrap_or(1.0),
+            }),
+            Projection::Orthographic(..) => None,
+        }
+    }
+
+    /// The direction the camera is looking in.
+    pub fn dir(&self) -> Vec3 {
+        match self.ty {
+            CameraType::LookAt(center) => (center - self.pos).normalize_or_zero(),
+            CameraType::LookTo(dir) => dir.normalize_or_zero(),
+        }
+    }
+
+    pub fn view(&self) -> Mat4 {
+        match self.ty {
+            CameraType::LookAt(center) => Mat4::look_at_rh(self.pos, center, Vec3::Y),
+            CameraType::LookTo(dir) => Mat4::look_to_rh(self.pos, dir, Vec3::Y),
+        }
+    }
+
+    pub fn proj(&self) -> Mat4 {
+        Mat4::perspective_infinite_rh(self.fov, self.aspect, NEAR)
+    }
+
     pub fn update_aspect(&mut self, data: &GameData) {
         self.aspect = data.get_window_size().x as f32 / data.get_window_size().y as f32;
     }
@@ -24,12 +61,7 @@ impl BufferCompatible for Camera {
 
 impl From<Camera> for [[f32; 4]; 4] {
     fn from(cam: Camera) -> Self {
-        let view = match cam.ty {
-            CameraType::LookAt(center) => Mat4::look_at_rh(cam.pos, center, Vec3::Y),
-            CameraType::LookTo(dir) => Mat4::look_to_rh(cam.pos, dir, Vec3::Y),
-        };
-        let proj = Mat4::perspective_infinite_rh(cam.fov, cam.aspect, 0.1);
-        (proj * view).to_cols_array_2d()
+        (cam.proj() * cam.view()).to_cols_array_2d()
     }
 }
 
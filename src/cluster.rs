@@ -0,0 +1,249 @@
+use glam::{Mat4, Vec2};
+use rhachis::GameData;
+use wgpu::{BindGroup, Buffer};
+
+use crate::{
+    camera::{Camera, NEAR},
+    light::{Light, LightType},
+};
+
+/// How many clusters the view is split into across, down and away from the
+/// camera.
+const GRID: [u32; 3] = [16, 9, 24];
+const CLUSTERS: usize = (GRID[0] * GRID[1] * GRID[2]) as usize;
+/// The depth the last slice of clusters starts to stretch to infinity at.
+const FAR: f32 = 500.0;
+
+/// Lights binned into froxels, boxes of the view that get smaller closer to
+/// the camera, so fragments only have to check the lights near them.
+pub(crate) struct Clusters {
+    /// A [`ClusterUniform`] describing the grid.
+    uniform: Buffer,
+    /// The offset into `indices` and number of lights of each cluster.
+    cells: Buffer,
+    /// The lights of each cluster, one after another.
+    indices: Buffer,
+    /// How many light indices fit in `indices`.
+    capacity: usize,
+    pub bind_group: BindGroup,
+    /// The lights in each cluster, kept between frames to reuse the
+    /// allocations.
+    lists: Vec<Vec<u32>>,
+}
+
+impl Clusters {
+    pub fn new(data: &GameData) -> Self {
+        let uniform = data.graphics.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("cluster_uniform"),
+            size: std::mem::size_of::<ClusterUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let cells = data.graphics.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("cluster_cells"),
+            size: CLUSTERS as u64 * 8,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let capacity = 1024;
+        let indices = Self::indices(data, capacity);
+        let bind_group = Self::bind_group(data, &uniform, &cells, &indices);
+
+        Self {
+            uniform,
+            cells,
+            indices,
+            capacity,
+            bind_group,
+            lists: vec![vec![]; CLUSTERS],
+        }
+    }
+
+    /// Bins every light into the clusters it reaches. Lights without a range
+    /// reach every cluster, so they should be given one where possible.
+    pub fn update(&mut self, data: &GameData, lights: &[Light], camera: &Camera) {
+        let view = camera.view();
+        let proj = camera.proj();
+        for list in &mut self.lists {
+            list.clear();
+        }
+
+        for (i, light) in lights.iter().enumerate() {
+            let [x, y, z] = match cluster_bounds(light, view, proj) {
+                Some(bounds) => bounds,
+                None => continue,
+            };
+            for z in z.0..=z.1 {
+                for y in y.0..=y.1 {
+                    for x in x.0..=x.1 {
+                        self.lists[cluster_index(x, y, z)].push(i as u32);
+                    }
+                }
+            }
+        }
+
+        let mut cells = Vec::with_capacity(CLUSTERS);
+        let mut indices = vec![];
+        for list in &self.lists {
+            cells.push([indices.len() as u32, list.len() as u32]);
+            indices.extend_from_slice(list);
+        }
+
+        if indices.len() > self.capacity {
+            self.capacity = indices.len().next_power_of_two();
+            self.indices = Self::indices(data, self.capacity);
+            self.bind_group = Self::bind_group(data, &self.uniform, &self.cells, &self.indices);
+        }
+
+        let size = data.get_window_size().as_vec2();
+        let uniform = ClusterUniform {
+            view: view.to_cols_array_2d(),
+            screen: [size.x, size.y, NEAR, FAR],
+            grid: [GRID[0], GRID[1], GRID[2], 0],
+        };
+        let queue = &data.graphics.queue;
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&uniform));
+        queue.write_buffer(&self.cells, 0, bytemuck::cast_slice(&cells));
+        if !indices.is_empty() {
+            queue.write_buffer(&self.indices, 0, bytemuck::cast_slice(&indices));
+        }
+    }
+
+    fn indices(data: &GameData, capacity: usize) -> Buffer {
+        data.graphics.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("cluster_indices"),
+            size: capacity as u64 * 4,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn bind_group(
+        data: &GameData,
+        uniform: &Buffer,
+        cells: &Buffer,
+        indices: &Buffer,
+    ) -> BindGroup {
+        data.graphics
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &Self::bind_group_layout(data),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: uniform.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: cells.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: indices.as_entire_binding(),
+                    },
+                ],
+            })
+    }
+
+    pub fn bind_group_layout(data: &GameData) -> wgpu::BindGroupLayout {
+        let storage = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        data.graphics
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    storage(1),
+                    storage(2),
+                ],
+            })
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ClusterUniform {
+    view: [[f32; 4]; 4],
+    /// The width and height of the screen, and the near and far depths of the
+    /// slices.
+    screen: [f32; 4],
+    grid: [u32; 4],
+}
+
+fn cluster_index(x: u32, y: u32, z: u32) -> usize {
+    ((z * GRID[1] + y) * GRID[0] + x) as usize
+}
+
+/// The slice of clusters a view space depth is in, slices get exponentially
+/// deeper so clusters stay roughly cube shaped.
+fn slice(depth: f32) -> u32 {
+    let slice = (depth.max(NEAR) / NEAR).ln() / (FAR / NEAR).ln() * GRID[2] as f32;
+    (slice as u32).min(GRID[2] - 1)
+}
+
+/// The first and last cluster a light reaches on each axis, or `None` if it
+/// can't be seen.
+fn cluster_bounds(light: &Light, view: Mat4, proj: Mat4) -> Option<[(u32, u32); 3]> {
+    let all = GRID.map(|x| (0, x - 1));
+    let range = match (light.ty, light.range) {
+        (LightType::Directional(_), _) | (_, None) => return Some(all),
+        (_, Some(range)) => range,
+    };
+
+    let center = view.transform_point3(light.pos);
+    let depth = -center.z;
+    if depth + range < NEAR {
+        return None;
+    }
+    let z = (slice(depth - range), slice(depth + range));
+    // The corners of a view space box around the light only bound it on the
+    // screen if they're all in front of the camera
+    if depth - range <= NEAR {
+        return Some([all[0], all[1], z]);
+    }
+
+    let (min, max) = [-1.0, 1.0]
+        .into_iter()
+        .flat_map(|x| [-1.0, 1.0].map(|y| Vec2::new(x, y)))
+        .flat_map(|xy| [-1.0, 1.0].map(|z| xy.extend(z)))
+        .map(|corner| {
+            let clip = proj * (center + corner * range).extend(1.0);
+            Vec2::new(clip.x, clip.y) / clip.w
+        })
+        .fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), x| (min.min(x), max.max(x)),
+        );
+    if min.x > 1.0 || min.y > 1.0 || max.x < -1.0 || max.y < -1.0 {
+        return None;
+    }
+
+    // Rows of clusters are counted from the top of the screen
+    let tile = |ndc: f32, count: u32| {
+        ((ndc * 0.5 + 0.5) * count as f32).clamp(0.0, count as f32 - 1.0) as u32
+    };
+    Some([
+        (tile(min.x, GRID[0]), tile(max.x, GRID[0])),
+        (tile(-max.y, GRID[1]), tile(-min.y, GRID[1])),
+        z,
+    ])
+}
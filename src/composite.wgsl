@@ -0,0 +1,24 @@
+// Covers the screen with a single triangle
+@vertex
+fn composite_vertex(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@group(0)@binding(0)
+var accum_texture: texture_2d<f32>;
+@group(0)@binding(1)
+var revealage_texture: texture_2d<f32>;
+
+@fragment
+fn composite_fragment(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(pos.xy);
+    let revealage = textureLoad(revealage_texture, coords, 0).r;
+    // Nothing transparent was drawn here
+    if revealage >= 0.9999 {
+        discard;
+    }
+    let accum = textureLoad(accum_texture, coords, 0);
+    let average = accum.rgb / max(accum.a, 0.00001);
+    return vec4<f32>(average, 1.0 - revealage);
+}
@@ -1,11 +1,10 @@
-struct VertexInput {
-    @location(0) pos: vec3<f32>,
-    @location(1) tex_coords: vec2<f32>,
-};
-
 struct VertexOutput {
     @builtin(position) pos: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    // The distance in front of the camera, which a perspective projection puts
+    // in w
+    @location(2) view_depth: f32,
 };
 
 struct Transform {
@@ -19,7 +18,8 @@ struct Transform {
 var<uniform> camera: Transform;
 
 @vertex
-fn texture_vertex(in: VertexInput, transform: Transform) -> VertexOutput {
+fn texture_vertex(input: VertexInput, transform: Transform) -> VertexOutput {
+    let in = vertex_input(input);
     let transform_matrix = mat4x4<f32>(
         transform.data0,
         transform.data1,
@@ -35,7 +35,9 @@ fn texture_vertex(in: VertexInput, transform: Transform) -> VertexOutput {
 
     var output: VertexOutput;
     output.pos = camera_matrix * transform_matrix * vec4<f32>(in.pos, 1.0);
-    output.tex_coords = in.tex_coords;
+    output.view_depth = output.pos.w;
+    output.tex_coords = material_uv(in);
+    output.color = in.color;
     return output;
 }
 
@@ -44,7 +46,42 @@ var color_texture: texture_2d<f32>;
 @group(0)@binding(1)
 var color_texture_sampler: sampler;
 
+// Discards cut out fragments and removes the alpha of opaque ones.
+fn alpha_mode(color: vec4<f32>) -> vec4<f32> {
+    if material.alpha_mode == 1u && color.a < material.alpha_cutoff {
+        discard;
+    }
+    if material.alpha_mode == 0u {
+        return vec4<f32>(color.rgb, 1.0);
+    }
+    return color;
+}
+
+fn texture_color(in: VertexOutput) -> vec4<f32> {
+    return alpha_mode(textureSample(color_texture, color_texture_sampler, in.tex_coords) * in.color);
+}
+
 @fragment
 fn texture_fragment(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(color_texture, color_texture_sampler, in.tex_coords);
+    return texture_color(in);
+}
+
+// The vertex color tinted by the material, without its texture
+fn flat_color(in: VertexOutput) -> vec4<f32> {
+    return alpha_mode(in.color * material.base_color);
+}
+
+@fragment
+fn color_fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    return flat_color(in);
+}
+
+@fragment
+fn texture_fragment_oit(in: VertexOutput) -> OitOutput {
+    return weighted_oit(texture_color(in), in.view_depth);
+}
+
+@fragment
+fn color_fragment_oit(in: VertexOutput) -> OitOutput {
+    return weighted_oit(flat_color(in), in.view_depth);
 }
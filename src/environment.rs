@@ -0,0 +1,484 @@
+use std::{num::NonZeroU32, path::Path};
+
+use image::{ImageResult, Rgb32FImage};
+use rhachis::GameData;
+use wgpu::{util::DeviceExt, BindGroup, RenderPipeline, Sampler, Texture, TextureView};
+
+/// The width and height of each face of the cube map the image is turned into
+/// before it's filtered.
+const CUBE_SIZE: u32 = 512;
+const CUBE_MIPS: u32 = CUBE_SIZE.trailing_zeros() + 1;
+const IRRADIANCE_SIZE: u32 = 32;
+const SPECULAR_SIZE: u32 = 128;
+/// How many mip levels the specular map has, from smooth to fully rough.
+const SPECULAR_MIPS: u32 = 5;
+const BRDF_SIZE: u32 = 256;
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const BRDF_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+/// An environment map filtered for lighting, used with
+/// [`Ambient::Environment`](crate::light::Ambient::Environment) to light and
+/// reflect the surroundings on models.
+#[derive(Debug)]
+pub struct Environment {
+    /// The light reaching surfaces facing each direction.
+    pub(crate) irradiance: TextureView,
+    /// The environment blurred more at each mip level, for rougher surfaces.
+    pub(crate) specular: TextureView,
+    /// How much of the specular light is reflected at each angle and
+    /// roughness.
+    pub(crate) brdf: TextureView,
+    pub(crate) sampler: Sampler,
+}
+
+impl Environment {
+    /// Loads an equirectangular image, usually a `.hdr` file.
+    pub fn from_path<P: AsRef<Path>>(data: &GameData, path: P) -> ImageResult<Self> {
+        Ok(Self::from_image(data, &image::open(path)?.into_rgb32f()))
+    }
+
+    /// Filters an equirectangular image on the GPU.
+    pub fn from_image(data: &GameData, image: &Rgb32FImage) -> Self {
+        let device = &data.graphics.device;
+        let filters = Filters::new(data);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("environment_encoder"),
+        });
+
+        let cube = equirect_cube(data, &filters, &mut encoder, image);
+        let source = filters.cube_bind_group(data, &cube_view(&cube, 0, CUBE_MIPS));
+
+        let irradiance = cube_texture(data, IRRADIANCE_SIZE, 1);
+        for face in 0..6 {
+            filters.pass(
+                data,
+                &mut encoder,
+                &filters.irradiance,
+                &source,
+                &face_view(&irradiance, face, 0),
+                Params::new(face, 0.0, CUBE_SIZE, IRRADIANCE_SIZE),
+            );
+        }
+
+        let specular = cube_texture(data, SPECULAR_SIZE, SPECULAR_MIPS);
+        for mip in 0..SPECULAR_MIPS {
+            let roughness = mip as f32 / (SPECULAR_MIPS - 1) as f32;
+            for face in 0..6 {
+                filters.pass(
+                    data,
+                    &mut encoder,
+                    &filters.specular,
+                    &source,
+                    &face_view(&specular, face, mip),
+                    Params::new(face, roughness, CUBE_SIZE, SPECULAR_SIZE >> mip),
+                );
+            }
+        }
+
+        let brdf = brdf_lut(data, &filters, &mut encoder, &source);
+
+        data.graphics.queue.submit([encoder.finish()]);
+
+        Self::from_maps(data, &irradiance, &specular, SPECULAR_MIPS, brdf)
+    }
+
+    /// An environment with no light, used when there isn't one. Textures
+    /// start out zeroed, so only the BRDF lookup table is drawn.
+    pub(crate) fn black(data: &GameData) -> Self {
+        let filters = Filters::new(data);
+        let mut encoder =
+            data.graphics
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("environment_encoder"),
+                });
+
+        let irradiance = cube_texture(data, 1, 1);
+        let specular = cube_texture(data, 1, 1);
+        // The lookup table doesn't read its source, any cube map will do
+        let source = filters.cube_bind_group(data, &cube_view(&irradiance, 0, 1));
+        let brdf = brdf_lut(data, &filters, &mut encoder, &source);
+
+        data.graphics.queue.submit([encoder.finish()]);
+
+        Self::from_maps(data, &irradiance, &specular, 1, brdf)
+    }
+
+    fn from_maps(
+        data: &GameData,
+        irradiance: &Texture,
+        specular: &Texture,
+        specular_mips: u32,
+        brdf: TextureView,
+    ) -> Self {
+        Self {
+            irradiance: cube_view(irradiance, 0, 1),
+            specular: cube_view(specular, 0, specular_mips),
+            brdf,
+            sampler: data
+                .graphics
+                .device
+                .create_sampler(&wgpu::SamplerDescriptor {
+                    label: Some("environment_sampler"),
+                    mag_filter: wgpu::FilterMode::Linear,
+                    min_filter: wgpu::FilterMode::Linear,
+                    mipmap_filter: wgpu::FilterMode::Linear,
+                    ..Default::default()
+                }),
+        }
+    }
+}
+
+/// Draws how much of the specular light is reflected at each angle and
+/// roughness, which is the same for every environment.
+fn brdf_lut(
+    data: &GameData,
+    filters: &Filters,
+    encoder: &mut wgpu::CommandEncoder,
+    source: &BindGroup,
+) -> TextureView {
+    let brdf = data
+        .graphics
+        .device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("environment_brdf"),
+            size: wgpu::Extent3d {
+                width: BRDF_SIZE,
+                height: BRDF_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: BRDF_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default());
+    filters.pass(
+        data,
+        encoder,
+        &filters.brdf,
+        source,
+        &brdf,
+        Params::new(0, 0.0, 0, BRDF_SIZE),
+    );
+    brdf
+}
+
+/// Turns an equirectangular image into a cube map without filtering it, for
+/// drawing as a skybox.
+pub(crate) fn equirect_to_cube(data: &GameData, image: &Rgb32FImage) -> TextureView {
+    let filters = Filters::new(data);
+    let mut encoder =
+        data.graphics
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("skybox_encoder"),
+            });
+    let cube = equirect_cube(data, &filters, &mut encoder, image);
+    data.graphics.queue.submit([encoder.finish()]);
+    cube_view(&cube, 0, CUBE_MIPS)
+}
+
+/// Draws an equirectangular image onto each face of a cube map, with mip
+/// levels to sample when filtering.
+fn equirect_cube(
+    data: &GameData,
+    filters: &Filters,
+    encoder: &mut wgpu::CommandEncoder,
+    image: &Rgb32FImage,
+) -> Texture {
+    let device = &data.graphics.device;
+    let pixels: Vec<f32> = image
+        .pixels()
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 1.0])
+        .collect();
+    let equirect = device
+        .create_texture_with_data(
+            &data.graphics.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("environment_equirect"),
+                size: wgpu::Extent3d {
+                    width: image.width().max(1),
+                    height: image.height().max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            },
+            bytemuck::cast_slice(&pixels),
+        )
+        .create_view(&wgpu::TextureViewDescriptor::default());
+    let equirect_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &filters.equirect_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 2,
+            resource: wgpu::BindingResource::TextureView(&equirect),
+        }],
+    });
+
+    let cube = cube_texture(data, CUBE_SIZE, CUBE_MIPS);
+    for face in 0..6 {
+        filters.pass(
+            data,
+            encoder,
+            &filters.equirect,
+            &equirect_bind_group,
+            &face_view(&cube, face, 0),
+            Params::new(face, 0.0, 0, CUBE_SIZE),
+        );
+    }
+    for mip in 1..CUBE_MIPS {
+        let source = filters.cube_bind_group(data, &cube_view(&cube, mip - 1, 1));
+        for face in 0..6 {
+            filters.pass(
+                data,
+                encoder,
+                &filters.downsample,
+                &source,
+                &face_view(&cube, face, mip),
+                Params::new(face, 0.0, CUBE_SIZE >> (mip - 1), CUBE_SIZE >> mip),
+            );
+        }
+    }
+    cube
+}
+
+/// The pipelines that filter an environment map.
+struct Filters {
+    equirect_layout: wgpu::BindGroupLayout,
+    cube_layout: wgpu::BindGroupLayout,
+    params_layout: wgpu::BindGroupLayout,
+    sampler: Sampler,
+    equirect: RenderPipeline,
+    downsample: RenderPipeline,
+    irradiance: RenderPipeline,
+    specular: RenderPipeline,
+    brdf: RenderPipeline,
+}
+
+impl Filters {
+    fn new(data: &GameData) -> Self {
+        let device = &data.graphics.device;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("environment.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("environment.wgsl").into()),
+        });
+
+        let texture = |binding, view_dimension, filterable| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let equirect_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[texture(2, wgpu::TextureViewDimension::D2, false)],
+        });
+        let cube_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                texture(0, wgpu::TextureViewDimension::Cube, true),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let params_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let pipeline = |source_layout, entry_point, format| {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[source_layout, &params_layout],
+                push_constant_ranges: &[],
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "environment_vertex",
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+            })
+        };
+
+        Self {
+            equirect: pipeline(&equirect_layout, "equirect_fragment", FORMAT),
+            downsample: pipeline(&cube_layout, "downsample_fragment", FORMAT),
+            irradiance: pipeline(&cube_layout, "irradiance_fragment", FORMAT),
+            specular: pipeline(&cube_layout, "specular_fragment", FORMAT),
+            brdf: pipeline(&cube_layout, "brdf_fragment", BRDF_FORMAT),
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }),
+            equirect_layout,
+            cube_layout,
+            params_layout,
+        }
+    }
+
+    fn cube_bind_group(&self, data: &GameData, view: &TextureView) -> BindGroup {
+        data.graphics
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.cube_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            })
+    }
+
+    /// Draws over the whole of `target` with a filter.
+    fn pass(
+        &self,
+        data: &GameData,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &RenderPipeline,
+        source: &BindGroup,
+        target: &TextureView,
+        params: Params,
+    ) {
+        let params = data
+            .graphics
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::bytes_of(&params),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let params = data
+            .graphics
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.params_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params.as_entire_binding(),
+                }],
+            });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("environment_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, source, &[]);
+        render_pass.set_bind_group(1, &params, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    face: u32,
+    roughness: f32,
+    source_size: f32,
+    target_size: f32,
+}
+
+impl Params {
+    fn new(face: u32, roughness: f32, source_size: u32, target_size: u32) -> Self {
+        Self {
+            face,
+            roughness,
+            source_size: source_size as f32,
+            target_size: target_size as f32,
+        }
+    }
+}
+
+fn cube_texture(data: &GameData, size: u32, mips: u32) -> Texture {
+    data.graphics
+        .device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("environment_cube"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: mips,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        })
+}
+
+fn cube_view(texture: &Texture, base_mip_level: u32, mips: u32) -> TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        base_mip_level,
+        mip_level_count: NonZeroU32::new(mips),
+        ..Default::default()
+    })
+}
+
+fn face_view(texture: &Texture, face: u32, mip: u32) -> TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2),
+        base_mip_level: mip,
+        mip_level_count: NonZeroU32::new(1),
+        base_array_layer: face,
+        array_layer_count: NonZeroU32::new(1),
+        ..Default::default()
+    })
+}
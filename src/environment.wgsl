@@ -0,0 +1,172 @@
+let PI: f32 = 3.14159265;
+
+// Covers the target with a single triangle
+@vertex
+fn environment_vertex(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+struct Params {
+    face: u32,
+    roughness: f32,
+    // The width of the faces of the source cube map
+    source_size: f32,
+    // The width of the target
+    target_size: f32,
+}
+
+@group(1)@binding(0)
+var<uniform> params: Params;
+
+@group(0)@binding(0)
+var source_cube: texture_cube<f32>;
+@group(0)@binding(1)
+var source_sampler: sampler;
+@group(0)@binding(2)
+var source_equirect: texture_2d<f32>;
+
+// The direction through a texel of a cube map face, following the way cube
+// maps are sampled
+fn face_direction(pos: vec4<f32>) -> vec3<f32> {
+    let uv = pos.xy / params.target_size * 2.0 - 1.0;
+    switch params.face {
+        case 0u: { return normalize(vec3<f32>(1.0, -uv.y, -uv.x)); }
+        case 1u: { return normalize(vec3<f32>(-1.0, -uv.y, uv.x)); }
+        case 2u: { return normalize(vec3<f32>(uv.x, 1.0, uv.y)); }
+        case 3u: { return normalize(vec3<f32>(uv.x, -1.0, -uv.y)); }
+        case 4u: { return normalize(vec3<f32>(uv.x, -uv.y, 1.0)); }
+        default: { return normalize(vec3<f32>(-uv.x, -uv.y, -1.0)); }
+    }
+}
+
+// Two directions perpendicular to `normal` and each other
+fn tangent_frame(normal: vec3<f32>) -> mat3x3<f32> {
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(normal.y) > 0.999 {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let tangent = normalize(cross(up, normal));
+    return mat3x3<f32>(tangent, cross(normal, tangent), normal);
+}
+
+@fragment
+fn equirect_fragment(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let dir = face_direction(pos);
+    let uv = vec2<f32>(atan2(dir.z, dir.x) / (2.0 * PI) + 0.5, acos(clamp(dir.y, -1.0, 1.0)) / PI);
+    let size = textureDimensions(source_equirect);
+    let coords = min(vec2<i32>(uv * vec2<f32>(size)), size - 1);
+    return vec4<f32>(textureLoad(source_equirect, coords, 0).rgb, 1.0);
+}
+
+// Averages the level above, which is the only one in the source view
+@fragment
+fn downsample_fragment(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    return textureSampleLevel(source_cube, source_sampler, face_direction(pos), 0.0);
+}
+
+// The light reaching a surface facing each direction, for the diffuse part of
+// the ambient light
+@fragment
+fn irradiance_fragment(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let frame = tangent_frame(face_direction(pos));
+    // Smaller levels avoid missing bright spots between samples
+    let level = max(log2(params.source_size / 32.0), 0.0);
+    let delta = 0.05;
+    var sum = vec3<f32>(0.0);
+    var count = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi += delta) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += delta) {
+            let local = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let color = textureSampleLevel(source_cube, source_sampler, frame * local, level).rgb;
+            sum += color * cos(theta) * sin(theta);
+            count += 1.0;
+        }
+    }
+    return vec4<f32>(PI * sum / count, 1.0);
+}
+
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// A halfway vector around `normal`, more likely where the GGX distribution is
+// larger
+fn importance_sample_ggx(xi: vec2<f32>, normal: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return tangent_frame(normal) * vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let denom = n_dot_h * n_dot_h * (a * a - 1.0) + 1.0;
+    return a * a / (PI * denom * denom);
+}
+
+// The environment blurred by the specular lobe of a roughness, assuming the
+// view is along the normal, from "Real Shading in Unreal Engine 4" by Karis
+@fragment
+fn specular_fragment(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let normal = face_direction(pos);
+    let samples = 256u;
+    // The solid angle covered by a texel of the source
+    let texel_angle = 4.0 * PI / (6.0 * params.source_size * params.source_size);
+    var sum = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < samples; i++) {
+        let halfway = importance_sample_ggx(hammersley(i, samples), normal, params.roughness);
+        let light = normalize(2.0 * dot(normal, halfway) * halfway - normal);
+        let n_dot_l = dot(normal, light);
+        if n_dot_l > 0.0 {
+            // Sampling smaller levels where samples are sparse avoids bright
+            // dots
+            let n_dot_h = max(dot(normal, halfway), 0.0);
+            let pdf = distribution_ggx(n_dot_h, params.roughness) * 0.25 + 0.0001;
+            let sample_angle = 1.0 / (f32(samples) * pdf + 0.0001);
+            var level = 0.0;
+            if params.roughness > 0.0 {
+                level = max(0.5 * log2(sample_angle / texel_angle), 0.0);
+            }
+            sum += textureSampleLevel(source_cube, source_sampler, light, level).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    return vec4<f32>(sum / max(weight, 0.0001), 1.0);
+}
+
+fn geometry_schlick_ggx(n_dot_v: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+// The scale and bias applied to the Fresnel reflectance at normal incidence,
+// for each angle to the view along x and roughness along y
+@fragment
+fn brdf_fragment(@builtin(position) pos: vec4<f32>) -> @location(0) vec2<f32> {
+    let uv = pos.xy / params.target_size;
+    let n_dot_v = max(uv.x, 0.001);
+    let roughness = uv.y;
+    let view = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let normal = vec3<f32>(0.0, 0.0, 1.0);
+    let samples = 256u;
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < samples; i++) {
+        let halfway = importance_sample_ggx(hammersley(i, samples), normal, roughness);
+        let light = normalize(2.0 * dot(view, halfway) * halfway - view);
+        let n_dot_l = max(light.z, 0.0);
+        let n_dot_h = max(halfway.z, 0.0);
+        let v_dot_h = max(dot(view, halfway), 0.0);
+        if n_dot_l > 0.0 {
+            let g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+            let g_vis = g * v_dot_h / (n_dot_h * n_dot_v);
+            let fresnel = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fresnel) * g_vis;
+            bias += fresnel * g_vis;
+        }
+    }
+    return vec2<f32>(scale, bias) / f32(samples);
+}
@@ -0,0 +1,291 @@
+use std::{collections::HashMap, fs, io, mem::size_of, path::Path, sync::Arc};
+
+use glam::Vec3;
+use image::{ImageOutputFormat, RgbaImage};
+use rhachis::GameData;
+use serde_json::{json, Value};
+
+use crate::{
+    material::{AlphaMode, Material, TextureTransform},
+    model::TextureVertex,
+    Renderer,
+};
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+impl Renderer {
+    /// Writes every model to a glTF file, with each instance becoming its own
+    /// node. Models without any triangles are left out. Paths ending in `.glb`
+    /// make a single binary file, otherwise the binary data is written to a
+    /// `.bin` file next to the `.gltf` file.
+    pub fn export_gltf<P: AsRef<Path>>(&self, data: &GameData, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut writer = GltfWriter::default();
+        for model in &self.models {
+            let vertices = model.read_vertices(data);
+            // Accessors can't be empty, so neither can primitives
+            if vertices.is_empty() || model.indices.values.is_empty() {
+                continue;
+            }
+            let mesh = writer.mesh(&vertices, &model.indices.values, &model.material)?;
+            for transform in &model.transforms.values {
+                writer.nodes.push(json!({
+                    "mesh": mesh,
+                    "translation": transform.translation.to_array(),
+                    "rotation": transform.rotation.to_array(),
+                    "scale": transform.scale.to_array(),
+                }));
+            }
+        }
+
+        let binary = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("glb"));
+        let buffer = match binary {
+            true => json!({ "byteLength": writer.bin.len() }),
+            false => {
+                let bin_path = path.with_extension("bin");
+                fs::write(&bin_path, &writer.bin)?;
+                json!({
+                    "uri": bin_path.file_name().unwrap().to_string_lossy(),
+                    "byteLength": writer.bin.len(),
+                })
+            }
+        };
+
+        let mut root = json!({
+            "asset": { "version": "2.0", "generator": "rare" },
+            "scene": 0,
+            "scenes": [{ "nodes": (0..writer.nodes.len()).collect::<Vec<_>>() }],
+            "nodes": writer.nodes,
+            "meshes": writer.meshes,
+            "materials": writer.materials,
+            "textures": writer.textures,
+            "images": writer.images,
+            "samplers": [{}],
+            "accessors": writer.accessors,
+            "bufferViews": writer.buffer_views,
+            "buffers": [buffer],
+        });
+        // Empty arrays aren't allowed
+        root.as_object_mut()
+            .unwrap()
+            .retain(|_, value| !matches!(value, Value::Array(array) if array.is_empty()));
+        if writer.images.is_empty() {
+            root.as_object_mut().unwrap().remove("samplers");
+        }
+        if writer.texture_transforms {
+            root["extensionsUsed"] = json!(["KHR_texture_transform"]);
+        }
+        let mut json = serde_json::to_vec(&root)?;
+
+        match binary {
+            true => {
+                // Chunks have to be padded to 4 bytes, JSON with spaces
+                json.resize(align(json.len()), b' ');
+                let mut bin = writer.bin;
+                bin.resize(align(bin.len()), 0);
+
+                let length = 12 + 8 + json.len() + 8 + bin.len();
+                let mut glb = Vec::with_capacity(length);
+                glb.extend_from_slice(b"glTF");
+                glb.extend_from_slice(&2u32.to_le_bytes());
+                glb.extend_from_slice(&(length as u32).to_le_bytes());
+                glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+                glb.extend_from_slice(b"JSON");
+                glb.extend_from_slice(&json);
+                glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+                glb.extend_from_slice(b"BIN\0");
+                glb.extend_from_slice(&bin);
+                fs::write(path, glb)
+            }
+            false => fs::write(path, json),
+        }
+    }
+}
+
+#[derive(Default)]
+struct GltfWriter {
+    bin: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    images: Vec<Value>,
+    textures: Vec<Value>,
+    materials: Vec<Value>,
+    meshes: Vec<Value>,
+    nodes: Vec<Value>,
+    /// Materials that have already been written, keyed by their address.
+    material_indices: HashMap<*const Material, usize>,
+    /// Whether `KHR_texture_transform` has to be listed as used.
+    texture_transforms: bool,
+}
+
+impl GltfWriter {
+    /// Adds a mesh with a single primitive, returning its index.
+    fn mesh(
+        &mut self,
+        vertices: &[TextureVertex],
+        indices: &[u32],
+        material: &Arc<Material>,
+    ) -> io::Result<usize> {
+        let (min, max) = vertices.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), vertex| {
+                let pos = Vec3::from(vertex.pos);
+                (min.min(pos), max.max(pos))
+            },
+        );
+
+        let view = self.buffer_view(
+            bytemuck::cast_slice(vertices),
+            Some(size_of::<TextureVertex>()),
+            ARRAY_BUFFER,
+        );
+        let mut attribute = |offset: usize, ty: &str| {
+            self.accessors.push(json!({
+                "bufferView": view,
+                "byteOffset": offset,
+                "componentType": FLOAT,
+                "count": vertices.len(),
+                "type": ty,
+            }));
+            self.accessors.len() - 1
+        };
+        let position = attribute(0, "VEC3");
+        let tex_coord = attribute(size_of::<[f32; 3]>(), "VEC2");
+        let normal = attribute(size_of::<[f32; 5]>(), "VEC3");
+        let color = attribute(size_of::<[f32; 8]>(), "VEC4");
+        let tex_coord_1 = attribute(size_of::<[f32; 12]>(), "VEC2");
+        // Bounds are required for positions
+        self.accessors[position]["min"] = json!(min.to_array());
+        self.accessors[position]["max"] = json!(max.to_array());
+
+        let view = self.buffer_view(bytemuck::cast_slice(indices), None, ELEMENT_ARRAY_BUFFER);
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        let indices = self.accessors.len() - 1;
+
+        let material = self.material(material)?;
+        self.meshes.push(json!({
+            "primitives": [{
+                "attributes": {
+                    "POSITION": position,
+                    "NORMAL": normal,
+                    "TEXCOORD_0": tex_coord,
+                    "COLOR_0": color,
+                    "TEXCOORD_1": tex_coord_1,
+                },
+                "indices": indices,
+                "material": material,
+            }],
+        }));
+        Ok(self.meshes.len() - 1)
+    }
+
+    /// Adds a material the first time it is used, returning its index.
+    fn material(&mut self, material: &Arc<Material>) -> io::Result<usize> {
+        if let Some(index) = self.material_indices.get(&Arc::as_ptr(material)) {
+            return Ok(*index);
+        }
+
+        let mut pbr = json!({
+            "metallicFactor": material.metallic,
+            "roughnessFactor": material.roughness,
+        });
+        if let Some(source) = &material.source {
+            pbr["baseColorTexture"] = self.texture_info(source, material.color_transform)?;
+        }
+
+        let mut converted = json!({ "pbrMetallicRoughness": pbr });
+        if let Some(source) = &material.occlusion_source {
+            converted["occlusionTexture"] =
+                self.texture_info(source, material.occlusion_transform)?;
+            converted["occlusionTexture"]["strength"] = json!(material.occlusion_strength);
+        }
+        match material.alpha_mode {
+            AlphaMode::Opaque => {}
+            AlphaMode::Mask(cutoff) => {
+                converted["alphaMode"] = json!("MASK");
+                converted["alphaCutoff"] = json!(cutoff);
+            }
+            AlphaMode::Blend => converted["alphaMode"] = json!("BLEND"),
+        }
+        if material.double_sided {
+            converted["doubleSided"] = json!(true);
+        }
+        self.materials.push(converted);
+        let index = self.materials.len() - 1;
+        self.material_indices.insert(Arc::as_ptr(material), index);
+        Ok(index)
+    }
+
+    /// Adds an image as a texture, returning a reference to it with the
+    /// texture coordinates and transform it's sampled with.
+    fn texture_info(
+        &mut self,
+        image: &RgbaImage,
+        transform: TextureTransform,
+    ) -> io::Result<Value> {
+        let mut info = json!({
+            "index": self.texture(image)?,
+            "texCoord": transform.tex_coord,
+        });
+        if transform != TextureTransform::default() {
+            info["extensions"] = json!({
+                "KHR_texture_transform": {
+                    "offset": transform.offset.to_array(),
+                    "rotation": transform.rotation,
+                    "scale": transform.scale.to_array(),
+                },
+            });
+            self.texture_transforms = true;
+        }
+        Ok(info)
+    }
+
+    /// Adds an image as a PNG, returning the index of a texture using it.
+    fn texture(&mut self, image: &RgbaImage) -> io::Result<usize> {
+        let mut png = io::Cursor::new(vec![]);
+        image
+            .write_to(&mut png, ImageOutputFormat::Png)
+            .map_err(io::Error::other)?;
+        let view = self.buffer_view(&png.into_inner(), None, 0);
+        self.images
+            .push(json!({ "bufferView": view, "mimeType": "image/png" }));
+        self.textures
+            .push(json!({ "source": self.images.len() - 1, "sampler": 0 }));
+        Ok(self.textures.len() - 1)
+    }
+
+    /// Appends data to the binary buffer, a `target` of 0 is left out.
+    fn buffer_view(&mut self, bytes: &[u8], stride: Option<usize>, target: u32) -> usize {
+        self.bin.resize(align(self.bin.len()), 0);
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": bytes.len(),
+        });
+        if let Some(stride) = stride {
+            view["byteStride"] = json!(stride);
+        }
+        if target != 0 {
+            view["target"] = json!(target);
+        }
+        self.bin.extend_from_slice(bytes);
+
+        self.buffer_views.push(view);
+        self.buffer_views.len() - 1
+    }
+}
+
+/// Rounds up to the 4 byte alignment glTF uses for buffer views and chunks.
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
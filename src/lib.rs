@@ -1,219 +1,89 @@
+pub mod background;
 pub mod camera;
+pub mod environment;
+pub mod export;
 pub mod light;
+pub mod lod;
 pub mod material;
+pub mod mesh;
 pub mod model;
+pub mod obj;
+pub mod ply;
+pub mod scene;
+pub mod shadow;
+pub mod stl;
+pub mod tonemap;
+pub mod vertex;
 
-use std::{path::Path, sync::Arc};
+mod cluster;
+mod oit;
+mod pipeline;
 
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use background::{Background, BackgroundPass};
 use camera::Camera;
+use cluster::Clusters;
+use environment::Environment;
 use glam::{Mat4, Vec3};
-use light::{Light, LightUniform};
-use material::Material;
-use model::{Model, TextureVertex};
+use light::{Ambient, Light, LightUniform};
+use material::{AlphaMode, Material};
+use model::Model;
+use oit::Oit;
+use pipeline::PipelineKey;
 use rhachis::{
-    graphics::{Bindable, BufferData, SamplerType},
-    renderers::{SimpleRenderer, Texture, Transform},
+    graphics::{Bindable, BufferData},
+    renderers::{SimpleRenderer, Texture},
     GameData, IdMap,
 };
-use wgpu::{BindGroup, Color, RenderPipeline};
+use shadow::Shadows;
+use tonemap::{Exposure, Hdr, Tonemapper};
+use wgpu::{util::DeviceExt, BindGroup, Buffer, Color, RenderPipeline};
 
 pub struct Renderer {
     pub models: IdMap<Model>,
     pub error_material: Arc<Material>,
     pub camera: BufferData<Camera>,
+    /// Cameras imported from glTF files, see [`Renderer::select_camera`].
+    pub cameras: Vec<Camera>,
+    pub camera_names: HashMap<String, usize>,
     pub lights: BufferData<Light>,
+    pub ambient: Ambient,
+    pub background: Background,
+    /// How [`Pipeline::Normal`] is tonemapped, the debug pipelines are only
+    /// clamped so their colors are shown as they are.
+    pub tonemapper: Tonemapper,
+    pub exposure: Exposure,
     pub pipeline: Pipeline,
+    /// How far past a threshold the screen size of a model has to go before
+    /// its level of detail changes, as a fraction of the threshold.
+    pub lod_hysteresis: f32,
+    pub transparency: Transparency,
+    /// The most point lights that cast shadows at once, the closest and
+    /// brightest ones are picked each frame.
+    pub max_point_shadows: usize,
     depth_texture: Texture,
     camera_bind_group: BindGroup,
     lights_bind_group: BindGroup,
-    texture_pipeline: RenderPipeline,
-    wireframe_pipeline: RenderPipeline,
-    render_pipeline: RenderPipeline,
+    ambient_buffer: Buffer,
+    /// The environment in the lights bind group, a black one until
+    /// [`Self::ambient`] uses one.
+    environment: Arc<Environment>,
+    clusters: Clusters,
+    background_pass: BackgroundPass,
+    oit: Oit,
+    /// The target models are drawn into before they're tonemapped onto the
+    /// screen.
+    hdr: Hdr,
+    shadows: Shadows,
+    /// Variants of each pipeline for every vertex layout that has been used.
+    pipelines: HashMap<PipelineKey, RenderPipeline>,
 }
 
 impl Renderer {
     pub fn new(data: &GameData) -> Self {
         let depth_texture = Texture::depth_texture(data, data.get_window_size());
 
-        let debug_shader =
-            data.graphics
-                .device
-                .create_shader_module(wgpu::ShaderModuleDescriptor {
-                    label: Some("debug.wgsl"),
-                    source: wgpu::ShaderSource::Wgsl(include_str!("debug.wgsl").into()),
-                });
-
-        let shader = data
-            .graphics
-            .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: Some("shader.wgsl"),
-                source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-            });
-
-        let texture_pipeline_layout =
-            data.graphics
-                .device
-                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: None,
-                    bind_group_layouts: &[
-                        &Material::bind_group_layout(data),
-                        &Transform::bind_group_layout(data),
-                    ],
-                    push_constant_ranges: &[],
-                });
-
-        let texture_pipeline =
-            data.graphics
-                .device
-                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                    label: Some("texture_pipeline"),
-                    layout: Some(&texture_pipeline_layout),
-                    vertex: wgpu::VertexState {
-                        module: &debug_shader,
-                        entry_point: "texture_vertex",
-                        buffers: &[TextureVertex::desc(), Transform::desc()],
-                    },
-                    primitive: wgpu::PrimitiveState {
-                        topology: wgpu::PrimitiveTopology::TriangleList,
-                        strip_index_format: None,
-                        front_face: wgpu::FrontFace::Ccw,
-                        cull_mode: Some(wgpu::Face::Back),
-                        unclipped_depth: false,
-                        polygon_mode: wgpu::PolygonMode::Fill,
-                        conservative: false,
-                    },
-                    depth_stencil: Some(wgpu::DepthStencilState {
-                        format: wgpu::TextureFormat::Depth32Float,
-                        depth_write_enabled: true,
-                        depth_compare: wgpu::CompareFunction::Less,
-                        stencil: wgpu::StencilState::default(),
-                        bias: wgpu::DepthBiasState::default(),
-                    }),
-                    multisample: wgpu::MultisampleState {
-                        count: 1,
-                        mask: !0,
-                        alpha_to_coverage_enabled: false,
-                    },
-                    fragment: Some(wgpu::FragmentState {
-                        entry_point: "texture_fragment",
-                        module: &debug_shader,
-                        targets: &[Some(wgpu::ColorTargetState {
-                            format: data.graphics.config.format,
-                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                            write_mask: wgpu::ColorWrites::ALL,
-                        })],
-                    }),
-                    multiview: None,
-                });
-
-        let wireframe_pipeline_layout =
-            data.graphics
-                .device
-                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: None,
-                    bind_group_layouts: &[
-                        &Material::bind_group_layout(data),
-                        &Transform::bind_group_layout(data),
-                    ],
-                    push_constant_ranges: &[],
-                });
-
-        let wireframe_pipeline =
-            data.graphics
-                .device
-                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                    label: Some("wireframe_pipeline"),
-                    layout: Some(&wireframe_pipeline_layout),
-                    vertex: wgpu::VertexState {
-                        module: &debug_shader,
-                        entry_point: "texture_vertex",
-                        buffers: &[TextureVertex::desc(), Transform::desc()],
-                    },
-                    primitive: wgpu::PrimitiveState {
-                        topology: wgpu::PrimitiveTopology::TriangleList,
-                        strip_index_format: None,
-                        front_face: wgpu::FrontFace::Ccw,
-                        cull_mode: None,
-                        unclipped_depth: false,
-                        polygon_mode: wgpu::PolygonMode::Line,
-                        conservative: false,
-                    },
-                    depth_stencil: None,
-                    multisample: wgpu::MultisampleState {
-                        count: 1,
-                        mask: !0,
-                        alpha_to_coverage_enabled: false,
-                    },
-                    fragment: Some(wgpu::FragmentState {
-                        entry_point: "texture_fragment",
-                        module: &debug_shader,
-                        targets: &[Some(wgpu::ColorTargetState {
-                            format: data.graphics.config.format,
-                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                            write_mask: wgpu::ColorWrites::ALL,
-                        })],
-                    }),
-                    multiview: None,
-                });
-
-        let render_pipeline_layout =
-            data.graphics
-                .device
-                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: None,
-                    bind_group_layouts: &[
-                        &Material::bind_group_layout(data),
-                        &Transform::bind_group_layout(data),
-                        &LightUniform::bind_group_layout(data),
-                    ],
-                    push_constant_ranges: &[],
-                });
-
-        let render_pipeline =
-            data.graphics
-                .device
-                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                    label: Some("render_pipeline"),
-                    layout: Some(&render_pipeline_layout),
-                    vertex: wgpu::VertexState {
-                        module: &shader,
-                        entry_point: "vertex_main",
-                        buffers: &[TextureVertex::desc(), Transform::desc()],
-                    },
-                    primitive: wgpu::PrimitiveState {
-                        topology: wgpu::PrimitiveTopology::TriangleList,
-                        strip_index_format: None,
-                        front_face: wgpu::FrontFace::Ccw,
-                        cull_mode: None,
-                        unclipped_depth: false,
-                        polygon_mode: wgpu::PolygonMode::Fill,
-                        conservative: false,
-                    },
-                    depth_stencil: Some(wgpu::DepthStencilState {
-                        format: wgpu::TextureFormat::Depth32Float,
-                        depth_write_enabled: true,
-                        depth_compare: wgpu::CompareFunction::Less,
-                        stencil: wgpu::StencilState::default(),
-                        bias: wgpu::DepthBiasState::default(),
-                    }),
-                    multisample: wgpu::MultisampleState {
-                        count: 1,
-                        mask: !0,
-                        alpha_to_coverage_enabled: false,
-                    },
-                    fragment: Some(wgpu::FragmentState {
-                        entry_point: "fragment_main",
-                        module: &shader,
-                        targets: &[Some(wgpu::ColorTargetState {
-                            format: data.graphics.config.format,
-                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                            write_mask: wgpu::ColorWrites::ALL,
-                        })],
-                    }),
-                    multiview: None,
-                });
-
         let camera = BufferData::new(
             data,
             vec![Camera::default()],
@@ -239,80 +109,160 @@ impl Renderer {
             vec![Light {
                 pos: Vec3::new(3.0, 0.0, 2.0),
                 color: Color::RED,
+                intensity: 10.0,
+                ..Default::default()
             }],
-            wgpu::BufferUsages::STORAGE,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         );
 
-        let lights_bind_group =
+        let max_point_shadows = 4;
+        let shadows = Shadows::new(data, lights.values.len(), max_point_shadows);
+        let ambient = Ambient::default();
+        let ambient_buffer =
             data.graphics
                 .device
-                .create_bind_group(&wgpu::BindGroupDescriptor {
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                     label: None,
-                    layout: &LightUniform::bind_group_layout(data),
-                    entries: &[wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::Buffer(
-                            lights.buffer.as_entire_buffer_binding(),
-                        ),
-                    }],
+                    contents: bytemuck::bytes_of(&ambient.uniform()),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
                 });
+        let environment = Arc::new(Environment::black(data));
+        let lights_bind_group =
+            Self::lights_bind_group(data, &lights, &shadows, &ambient_buffer, &environment);
 
         Self {
             models: IdMap::new(),
             error_material: Arc::new(Material::error(data)),
             camera,
+            cameras: vec![],
+            camera_names: HashMap::new(),
             lights,
+            ambient,
             pipeline: Pipeline::Normal,
+            lod_hysteresis: 0.1,
+            transparency: Transparency::Sorted,
+            max_point_shadows,
+            clusters: Clusters::new(data),
+            background: Background::default(),
+            background_pass: BackgroundPass::new(data),
+            tonemapper: Tonemapper::default(),
+            exposure: Exposure::default(),
+            oit: Oit::new(data, data.get_window_size()),
+            hdr: Hdr::new(data, data.get_window_size()),
+            shadows,
             depth_texture,
             camera_bind_group,
             lights_bind_group,
-            texture_pipeline,
-            wireframe_pipeline,
-            render_pipeline,
+            ambient_buffer,
+            environment,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Makes the imported camera at `index` in [`Self::cameras`] the active
+    /// camera, returning `false` if there is no camera at that index.
+    pub fn select_camera(&mut self, data: &GameData, index: usize) -> bool {
+        match self.cameras.get(index) {
+            Some(camera) => {
+                self.camera.values[0] = *camera;
+                self.camera.values[0].update_aspect(data);
+                self.camera.update(data);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Makes the imported camera called `name` the active camera, returning
+    /// `false` if no camera has that name.
+    pub fn select_camera_by_name(&mut self, data: &GameData, name: &str) -> bool {
+        match self.camera_names.get(name) {
+            Some(&index) => self.select_camera(data, index),
+            None => false,
+        }
+    }
+
+    /// Uploads the current contents of [`Self::lights`] to the GPU, this has
+    /// to be called after lights are added or removed.
+    pub fn update_lights(&mut self, data: &GameData) {
+        let usage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST;
+        self.lights = BufferData::new(data, std::mem::take(&mut self.lights.values), usage);
+        // Storage buffers can't be empty, so there's always room for one light
+        if self.lights.values.is_empty() {
+            self.lights.buffer = data.graphics.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: std::mem::size_of::<LightUniform>() as u64,
+                usage,
+                mapped_at_creation: false,
+            });
         }
+        self.shadows.resize(data, self.lights.values.len());
+        self.lights_bind_group = Self::lights_bind_group(
+            data,
+            &self.lights,
+            &self.shadows,
+            &self.ambient_buffer,
+            &self.environment,
+        );
     }
 
-    pub fn load_gltf<P: AsRef<Path>>(
-        &mut self,
+    fn lights_bind_group(
         data: &GameData,
-        path: P,
-        scene: usize,
-    ) -> Vec<usize> {
-        let scene = &easy_gltf::load(path).unwrap()[scene];
-        self.models.append(
-            scene
-                .models
-                .iter()
-                .map(|model| {
-                    let vertices = model
-                        .vertices()
-                        .iter()
-                        .map(|vertex| TextureVertex {
-                            pos: vertex.position.into(),
-                            tex_coords: vertex.tex_coords.into(),
-                            normals: vertex.normal.into(),
-                        })
-                        .collect();
-                    let indices = model
-                        .indices()
-                        .unwrap()
-                        .iter()
-                        .map(|index| *index as u16)
-                        .collect();
-                    let material = Arc::new(Material {
-                        color: Texture::from_path(data, "examples/test.png", &SamplerType::Linear)
-                            .unwrap(),
-                    });
-                    Model::new(
-                        data,
-                        vertices,
-                        indices,
-                        material,
-                        vec![Transform::default()],
-                    )
-                })
-                .collect(),
-        )
+        lights: &BufferData<Light>,
+        shadows: &Shadows,
+        ambient: &Buffer,
+        environment: &Environment,
+    ) -> BindGroup {
+        data.graphics
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &LightUniform::bind_group_layout(data),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer(
+                            lights.buffer.as_entire_buffer_binding(),
+                        ),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: shadows.uniforms.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(shadows.atlas()),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::Sampler(&shadows.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: wgpu::BindingResource::TextureView(shadows.cubes()),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: ambient.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: wgpu::BindingResource::TextureView(&environment.irradiance),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 7,
+                        resource: wgpu::BindingResource::TextureView(&environment.specular),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 8,
+                        resource: wgpu::BindingResource::TextureView(&environment.brdf),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 9,
+                        resource: wgpu::BindingResource::Sampler(&environment.sampler),
+                    },
+                ],
+            })
     }
 
     pub fn with_gltf<P: AsRef<Path>>(mut self, data: &GameData, path: P, scene: usize) -> Self {
@@ -320,71 +270,263 @@ impl Renderer {
         self
     }
 
-    pub const FEATURES: wgpu::Features = wgpu::Features::POLYGON_MODE_LINE;
-}
+    /// Sets the pipeline and bind groups for a model, returning `false` if its
+    /// pipeline hasn't been made yet, as that only happens in `update`.
+    fn bind_model<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, model: &'a Model) -> bool {
+        let key = PipelineKey::new(self.pipeline, model, self.uses_oit());
+        let pipeline = match self.pipelines.get(&key) {
+            Some(pipeline) => pipeline,
+            None => return false,
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &model.material.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+        if self.pipeline.lit() {
+            render_pass.set_bind_group(2, &self.lights_bind_group, &[]);
+            render_pass.set_bind_group(3, &self.clusters.bind_group, &[]);
+        }
+        true
+    }
 
-impl rhachis::graphics::Renderer for Renderer {
-    fn render<'a, 'b: 'a>(&'b self, render_pass: &'a mut wgpu::RenderPass<'b>) {
-        macro_rules! default_render_routine {
-            () => {
-                for model in &self.models {
-                    render_pass.set_vertex_buffer(0, model.vertex_buffer.slice(..));
-                    render_pass.set_vertex_buffer(1, model.transforms.buffer.slice(..));
-                    render_pass.set_index_buffer(
-                        model.indices.buffer.slice(..),
-                        wgpu::IndexFormat::Uint16,
-                    );
-                    render_pass.set_bind_group(0, &model.material.color.bind_group, &[]);
-                    render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-                    render_pass.draw_indexed(0..model.indices.buffer_len, 0, 0..1);
-                }
-            };
+    /// The wireframe pipeline has no depth buffer to test transparent
+    /// fragments against, so it always sorts instead.
+    fn uses_oit(&self) -> bool {
+        self.transparency == Transparency::WeightedBlended && self.pipeline != Pipeline::Wireframe
+    }
+
+    /// Draws opaque models, then accumulates the transparent ones and
+    /// composites them over the top.
+    fn draw_oit(&self, encoder: &mut wgpu::CommandEncoder) {
+        let view = &self.hdr.view;
+        let depth_attachment = |load| {
+            Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_texture.view,
+                depth_ops: Some(wgpu::Operations { load, store: true }),
+                stencil_ops: None,
+            })
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("opaque_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: depth_attachment(wgpu::LoadOp::Clear(1.0)),
+        });
+        for model in &self.models {
+            if model.material.alpha_mode != AlphaMode::Blend
+                && self.bind_model(&mut render_pass, model)
+            {
+                model.draw(&mut render_pass);
+            }
         }
-        match self.pipeline {
-            Pipeline::Normal => {
-                render_pass.set_pipeline(&self.render_pipeline);
-                render_pass.set_bind_group(2, &self.lights_bind_group, &[]);
-                default_render_routine!();
+        self.background_pass.draw(&mut render_pass, true);
+        drop(render_pass);
+
+        let target = |view, clear| {
+            Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear),
+                    store: true,
+                },
+            })
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("accum_pass"),
+            color_attachments: &[
+                target(&self.oit.accum, Color::TRANSPARENT),
+                target(&self.oit.revealage, Color::WHITE),
+            ],
+            depth_stencil_attachment: depth_attachment(wgpu::LoadOp::Load),
+        });
+        for model in &self.models {
+            if model.material.alpha_mode == AlphaMode::Blend
+                && self.bind_model(&mut render_pass, model)
+            {
+                model.draw(&mut render_pass);
             }
-            Pipeline::Texture => {
-                render_pass.set_pipeline(&self.texture_pipeline);
-                default_render_routine!();
+        }
+        drop(render_pass);
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("composite_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        self.oit.composite(&mut render_pass);
+    }
+
+    /// Draws opaque models, then the blended ones sorted from back to front.
+    fn draw_sorted(&self, encoder: &mut wgpu::CommandEncoder) {
+        // Without a depth buffer the models can only be drawn over the
+        // background
+        let depth = self.pipeline != Pipeline::Wireframe;
+        let mut render_pass = SimpleRenderer::render_pass(
+            &self.hdr.view,
+            encoder,
+            depth.then_some(&self.depth_texture.view),
+        );
+        if !depth {
+            self.background_pass.draw(&mut render_pass, false);
+        }
+
+        let camera = self.camera.values[0].pos;
+        let mut blended = vec![];
+        for model in &self.models {
+            if model.material.alpha_mode == AlphaMode::Blend {
+                blended.extend(
+                    model
+                        .instance_positions()
+                        .enumerate()
+                        .map(|(i, pos)| (pos.distance_squared(camera), model, i as u32)),
+                );
+            } else if self.bind_model(&mut render_pass, model) {
+                model.draw(&mut render_pass);
             }
-            Pipeline::Wireframe => {
-                render_pass.set_pipeline(&self.wireframe_pipeline);
-                default_render_routine!();
+        }
+
+        if depth {
+            self.background_pass.draw(&mut render_pass, true);
+        }
+
+        // Blended instances are drawn from back to front
+        blended.sort_by(|a, b| b.0.total_cmp(&a.0));
+        for (_, model, instance) in blended {
+            if self.bind_model(&mut render_pass, model) {
+                model.draw_instance(&mut render_pass, instance);
             }
-            _ => todo!(),
         }
     }
 
+    pub const FEATURES: wgpu::Features = wgpu::Features::POLYGON_MODE_LINE;
+}
+
+impl rhachis::graphics::Renderer for Renderer {
+    fn render<'a, 'b: 'a>(&'b self, render_pass: &'a mut wgpu::RenderPass<'b>) {
+        // Models have already been drawn into the HDR target in
+        // `make_render_pass`
+        self.hdr.resolve(render_pass);
+    }
+
     fn make_render_pass<'a>(
         &'a self,
         view: &'a wgpu::TextureView,
         encoder: &'a mut wgpu::CommandEncoder,
     ) -> wgpu::RenderPass {
-        let depth_texture = match self.pipeline {
-            Pipeline::Wireframe => None,
-            _ => Some(&self.depth_texture.view),
-        };
-        SimpleRenderer::render_pass(view, encoder, depth_texture)
+        if self.pipeline.lit() {
+            self.shadows.render(encoder, &self.models);
+        }
+
+        match self.uses_oit() {
+            true => self.draw_oit(encoder),
+            false => self.draw_sorted(encoder),
+        }
+        self.hdr.measure(encoder);
+
+        SimpleRenderer::render_pass(view, encoder, None)
     }
 
     fn update(&mut self, data: &GameData) {
         self.camera.values[0].update_aspect(data);
+        let camera = self.camera.values[0];
+        let oit = self.uses_oit();
+        let mut rebind = false;
+        if self.shadows.point_shadows() != self.max_point_shadows {
+            self.shadows.set_point_shadows(data, self.max_point_shadows);
+            rebind = true;
+        }
+        if let Ambient::Environment { environment, .. } = &self.ambient {
+            if !Arc::ptr_eq(environment, &self.environment) {
+                self.environment = environment.clone();
+                rebind = true;
+            }
+        }
+        if rebind {
+            self.lights_bind_group = Self::lights_bind_group(
+                data,
+                &self.lights,
+                &self.shadows,
+                &self.ambient_buffer,
+                &self.environment,
+            );
+        }
+        self.shadows.update(data, &self.lights.values, &camera);
+        self.background_pass.update(data, &self.background, &camera);
+        match self.pipeline {
+            Pipeline::Normal => self.hdr.update(data, self.tonemapper, self.exposure),
+            _ => self
+                .hdr
+                .update(data, Tonemapper::Clamp, Exposure::Manual(0.0)),
+        }
+        if self.pipeline.lit() {
+            self.clusters.update(data, &self.lights.values, &camera);
+            data.graphics.queue.write_buffer(
+                &self.ambient_buffer,
+                0,
+                bytemuck::bytes_of(&self.ambient.uniform()),
+            );
+        }
         for model in &mut self.models {
             model.transforms.update(data);
+            self.shadows.prepare(data, model);
+            self.pipelines
+                .entry(PipelineKey::new(self.pipeline, model, oit))
+                .or_insert_with_key(|key| key.create(data));
+            if !model.lods.is_empty() {
+                model.update_lods(data, &camera, self.lod_hysteresis);
+            }
         }
     }
 
     fn resize(&mut self, data: &GameData, size: glam::UVec2) {
         self.depth_texture = Texture::depth_texture(data, size);
+        self.oit.resize(data, size);
+        self.hdr.resize(data, size);
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Pipeline {
     Normal,
     Texture,
     Wireframe,
+    /// The vertex colors times the average color of the material, without
+    /// textures or lighting.
     Color,
+    /// Colors models by how many lights reach each part of them, from blue
+    /// with none to red with 16 or more.
+    LightCount,
+}
+
+impl Pipeline {
+    /// Whether the pipeline uses the lights, and so their bind groups.
+    pub(crate) fn lit(self) -> bool {
+        matches!(self, Self::Normal | Self::LightCount)
+    }
+}
+
+/// How materials with [`AlphaMode::Blend`] are drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Transparency {
+    /// Instances are sorted from back to front, which can be wrong for
+    /// instances that overlap.
+    #[default]
+    Sorted,
+    /// Weighted blended order independent transparency, which doesn't need
+    /// sorting but only approximates the order of overlapping surfaces.
+    WeightedBlended,
 }
@@ -1,11 +1,141 @@
-use glam::Vec3;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use gltf::khr_lights_punctual;
 use rhachis::graphics::{Bindable, BufferCompatible};
 use wgpu::Color;
 
+use crate::{environment::Environment, shadow::ShadowSettings};
+
 #[derive(Clone, Copy, Debug)]
 pub struct Light {
     pub pos: Vec3,
+    /// A linear color, like the ones in glTF.
     pub color: Color,
+    pub ty: LightType,
+    pub intensity: f32,
+    /// Distance at which the light stops having an effect, `None` means the
+    /// light reaches infinitely far.
+    pub range: Option<f32>,
+    /// Lights only cast shadows if this is set.
+    pub shadows: Option<ShadowSettings>,
+}
+
+impl Light {
+    /// Converts a `KHR_lights_punctual` light attached to a node with the
+    /// world space `transform`.
+    pub fn from_gltf(light: &khr_lights_punctual::Light, transform: Mat4) -> Self {
+        let pos = transform.transform_point3(Vec3::ZERO);
+        let dir = transform.transform_vector3(Vec3::NEG_Z).normalize_or_zero();
+        let [r, g, b] = light.color();
+
+        Self {
+            pos,
+            color: Color {
+                r: r as f64,
+                g: g as f64,
+                b: b as f64,
+                a: 1.0,
+            },
+            ty: match light.kind() {
+                khr_lights_punctual::Kind::Directional => LightType::Directional(dir),
+                khr_lights_punctual::Kind::Point => LightType::Point,
+                khr_lights_punctual::Kind::Spot {
+                    inner_cone_angle,
+                    outer_cone_angle,
+                } => LightType::Spot {
+                    dir,
+                    inner_angle: inner_cone_angle,
+                    outer_angle: outer_cone_angle,
+                },
+            },
+            intensity: light.intensity(),
+            range: light.range(),
+            shadows: None,
+        }
+    }
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            pos: Vec3::ZERO,
+            color: Color::WHITE,
+            ty: LightType::Point,
+            intensity: 1.0,
+            range: None,
+            shadows: None,
+        }
+    }
+}
+
+/// Light reaching every surface from all around, so the sides facing away from
+/// every light aren't black. The colors are linear.
+#[derive(Clone, Debug)]
+pub enum Ambient {
+    Color(Color),
+    /// Blends from `ground` on surfaces facing down to `sky` on surfaces
+    /// facing up.
+    Hemisphere {
+        sky: Color,
+        ground: Color,
+    },
+    /// Diffuse light and reflections from the surroundings, scaled by
+    /// `intensity`.
+    Environment {
+        environment: Arc<Environment>,
+        intensity: f32,
+    },
+}
+
+impl Ambient {
+    /// The sky and ground colors, and the intensity of the environment, used
+    /// by the shader.
+    pub(crate) fn uniform(&self) -> [[f32; 4]; 3] {
+        let color = |color: &Color| [color.r, color.g, color.b, color.a].map(|x| x as f32);
+        match self {
+            Self::Color(flat) => [color(flat), color(flat), [0.0; 4]],
+            Self::Hemisphere { sky, ground } => [color(sky), color(ground), [0.0; 4]],
+            Self::Environment { intensity, .. } => {
+                [[0.0; 4], [0.0; 4], [*intensity, 0.0, 0.0, 0.0]]
+            }
+        }
+    }
+}
+
+impl Default for Ambient {
+    fn default() -> Self {
+        Self::Color(Color {
+            r: 0.05,
+            g: 0.05,
+            b: 0.05,
+            a: 1.0,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum LightType {
+    Point,
+    /// A light infinitely far away shining in the contained direction.
+    Directional(Vec3),
+    /// A cone of light, the angles are measured from the center of the cone
+    /// in radians.
+    Spot {
+        dir: Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+impl LightType {
+    fn id(&self) -> f32 {
+        match self {
+            Self::Point => 0.0,
+            Self::Directional(..) => 1.0,
+            Self::Spot { .. } => 2.0,
+        }
+    }
 }
 
 impl BufferCompatible for Light {
@@ -20,38 +150,127 @@ impl BufferCompatible for Light {
 pub struct LightUniform {
     pub pos: [f32; 4],
     pub color: [f32; 4],
+    pub direction: [f32; 4],
+    pub cone: [f32; 4],
 }
 
 impl Bindable for LightUniform {
     fn bind_group_layout(data: &rhachis::GameData) -> wgpu::BindGroupLayout {
+        let texture = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
         data.graphics
             .device
             .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: None,
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Shadows for each light
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                        count: None,
                     },
-                    count: None,
-                }],
+                    // Cube maps of point lights
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::CubeArray,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // Ambient light
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // The irradiance and specular maps, BRDF lookup table and
+                    // sampler of the environment
+                    texture(6, wgpu::TextureViewDimension::Cube),
+                    texture(7, wgpu::TextureViewDimension::Cube),
+                    texture(8, wgpu::TextureViewDimension::D2),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 9,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
             })
     }
 }
 
 impl From<Light> for LightUniform {
     fn from(value: Light) -> Self {
+        let (direction, cone) = match value.ty {
+            LightType::Point => (Vec3::ZERO, [0.0; 4]),
+            LightType::Directional(dir) => (dir, [0.0; 4]),
+            LightType::Spot {
+                dir,
+                inner_angle,
+                outer_angle,
+            } => (dir, [inner_angle.cos(), outer_angle.cos(), 0.0, 0.0]),
+        };
+
         LightUniform {
-            pos: value.pos.extend(0.0).to_array(),
+            pos: value.pos.extend(value.ty.id()).to_array(),
             color: [
                 value.color.r as f32,
                 value.color.g as f32,
                 value.color.b as f32,
-                0.0,
+                value.intensity,
             ],
+            direction: direction
+                .normalize_or_zero()
+                .extend(value.range.unwrap_or(0.0))
+                .to_array(),
+            cone,
         }
     }
 }
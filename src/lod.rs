@@ -0,0 +1,115 @@
+use std::ops::Range;
+
+use rhachis::{graphics::BufferData, renderers::Transform, GameData};
+
+use crate::{camera::Camera, mesh::Mesh, model::Model};
+
+/// A simplified version of a [`Model`] sharing its vertices.
+pub struct Lod {
+    pub indices: BufferData<u32>,
+    /// The fraction of the screen height the model has to cover less than for
+    /// this level to be used.
+    pub screen_size: f32,
+}
+
+/// Settings for generating a [`Lod`] with [`Model::generate_lods`].
+#[derive(Clone, Copy, Debug)]
+pub struct LodLevel {
+    /// How many triangles to keep compared to the full model.
+    pub triangle_ratio: f32,
+    pub screen_size: f32,
+}
+
+/// Instances of a model sorted by their level of detail, so each level can
+/// be drawn with a single call.
+pub(crate) struct LodInstances {
+    levels: Vec<usize>,
+    pub transforms: BufferData<Transform>,
+    /// The instances in `transforms` using each level, starting with the full
+    /// model.
+    pub ranges: Vec<Range<u32>>,
+}
+
+impl Model {
+    /// Simplifies the model into a [`Lod`] for each level, the levels should
+    /// be ordered from most to least detailed. Levels stop being made once
+    /// simplifying can't remove any more triangles, so there can be fewer
+    /// than asked for. This reads the vertices back from the GPU so it
+    /// shouldn't be called every frame.
+    pub fn generate_lods(&mut self, data: &GameData, levels: &[LodLevel]) {
+        let mesh = Mesh::new(self.read_vertices(data), self.indices.values.clone());
+        let triangles = mesh.indices.len() / 3;
+
+        self.lods.clear();
+        let mut previous_len = mesh.indices.len();
+        for level in levels {
+            let indices = mesh.simplify((triangles as f32 * level.triangle_ratio) as usize);
+            if indices.is_empty() || indices.len() >= previous_len {
+                break;
+            }
+            previous_len = indices.len();
+            self.lods.push(Lod {
+                indices: BufferData::new(data, indices, wgpu::BufferUsages::INDEX),
+                screen_size: level.screen_size,
+            });
+        }
+        self.lod_instances = None;
+    }
+
+    /// Picks the level of each instance based on how much of the screen it
+    /// covers. Levels only change once the size is past the threshold by the
+    /// fraction `hysteresis`, to stop models flickering between levels.
+    pub(crate) fn update_lods(&mut self, data: &GameData, camera: &Camera, hysteresis: f32) {
+        let instances = self.transforms.values.len();
+        let lod_instances = match &mut self.lod_instances {
+            Some(lod_instances) if lod_instances.levels.len() == instances => lod_instances,
+            lod_instances => lod_instances.insert(LodInstances {
+                levels: vec![0; instances],
+                transforms: BufferData::new(
+                    data,
+                    self.transforms.values.clone(),
+                    wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                ),
+                ranges: vec![],
+            }),
+        };
+
+        let half_height = (camera.fov / 2.0).tan();
+        for (level, transform) in lod_instances.levels.iter_mut().zip(&self.transforms.values) {
+            let radius = self.radius * transform.scale.abs().max_element();
+            let distance = camera.pos.distance(transform.translation) * half_height;
+            let screen_size = radius / distance.max(f32::EPSILON);
+
+            while *level > 0 && screen_size > self.lods[*level - 1].screen_size * (1.0 + hysteresis)
+            {
+                *level -= 1;
+            }
+            while *level < self.lods.len()
+                && screen_size < self.lods[*level].screen_size * (1.0 - hysteresis)
+            {
+                *level += 1;
+            }
+        }
+
+        let mut order: Vec<usize> = (0..instances).collect();
+        order.sort_by_key(|instance| lod_instances.levels[*instance]);
+        lod_instances.transforms.values = order
+            .iter()
+            .map(|instance| self.transforms.values[*instance])
+            .collect();
+        lod_instances.transforms.update(data);
+
+        let mut start = 0;
+        lod_instances.ranges = (0..=self.lods.len())
+            .map(|level| {
+                let count = lod_instances
+                    .levels
+                    .iter()
+                    .filter(|instance_level| **instance_level == level)
+                    .count() as u32;
+                start += count;
+                start - count..start
+            })
+            .collect();
+    }
+}
@@ -1,22 +1,262 @@
-use image::ImageError;
-use rhachis::{renderers::Texture, GameData};
+use std::path::Path;
+
+use bytemuck::Zeroable;
+use glam::Vec2;
+use image::{ImageError, Rgba, RgbaImage};
+use rhachis::{graphics::SamplerType, GameData};
+use wgpu::{util::DeviceExt, BindGroup, Buffer, Sampler, TextureView};
 
 pub struct Material {
-    pub color: Texture,
+    /// The color of the material, stored as sRGB unless changed with
+    /// [`Material::with_color_space`].
+    pub color: MaterialTexture,
+    /// The image `color` was made from, kept so the material can be exported.
+    pub source: Option<RgbaImage>,
+    /// Which texture coordinates `color` uses, [`Material::update`] has to be
+    /// called after changing it.
+    pub color_transform: TextureTransform,
+    /// How the alpha of the color is used, [`Material::update`] has to be
+    /// called after changing it.
+    pub alpha_mode: AlphaMode,
+    /// Draws the back of triangles as well, with their normals flipped.
+    pub double_sided: bool,
+    /// Darkens the ambient light reaching the material by its red channel.
+    /// This is white unless set with [`Material::with_occlusion`]. This is
+    /// always linear.
+    pub occlusion: MaterialTexture,
+    /// The image `occlusion` was made from, kept so the material can be
+    /// exported.
+    pub occlusion_source: Option<RgbaImage>,
+    /// Which texture coordinates `occlusion` uses, [`Material::update`] has to
+    /// be called after changing it.
+    pub occlusion_transform: TextureTransform,
+    /// Blends from no occlusion at 0 to all of it at 1,
+    /// [`Material::update`] has to be called after changing it.
+    pub occlusion_strength: f32,
+    /// How metallic the material is from 0 to 1, [`Material::update`] has to
+    /// be called after changing it.
+    pub metallic: f32,
+    /// How rough the material is from 0 to 1, [`Material::update`] has to be
+    /// called after changing it.
+    pub roughness: f32,
+    /// The average linear color of `source`, worked out when the color
+    /// texture is made.
+    base_color: [f32; 4],
+    sampler: Sampler,
+    params: Buffer,
+    pub(crate) bind_group: BindGroup,
 }
 
 impl Material {
     pub fn error(data: &GameData) -> Material {
         let error_image = image::load_from_memory(include_bytes!("error.png")).unwrap();
 
-        Self {
-            color: Texture::from_image(
+        Self::from_image(data, error_image.into_rgba8(), &SamplerType::Nearest)
+    }
+
+    pub fn from_image(data: &GameData, image: RgbaImage, sampler: &SamplerType) -> Material {
+        let color = MaterialTexture::from_image(data, &image, ColorSpace::Srgb);
+        let color_transform = TextureTransform::default();
+        let alpha_mode = AlphaMode::default();
+        let occlusion = MaterialTexture::from_image(
+            data,
+            &RgbaImage::from_pixel(1, 1, Rgba([255; 4])),
+            ColorSpace::Linear,
+        );
+
+        // Filled in by `update` once the material is made
+        let params = data
+            .graphics
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::bytes_of(&MaterialUniform::zeroed()),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        // Texture transforms rely on textures repeating
+        let filter = match sampler {
+            SamplerType::Linear => wgpu::FilterMode::Linear,
+            SamplerType::Nearest => wgpu::FilterMode::Nearest,
+        };
+        let sampler = data
+            .graphics
+            .device
+            .create_sampler(&wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::Repeat,
+                address_mode_v: wgpu::AddressMode::Repeat,
+                mag_filter: filter,
+                min_filter: filter,
+                ..Default::default()
+            });
+
+        let bind_group = Self::bind_group(data, &color, &occlusion, &sampler, &params);
+
+        let material = Self {
+            color,
+            base_color: average_color(&image, ColorSpace::Srgb),
+            source: Some(image),
+            color_transform,
+            alpha_mode,
+            double_sided: false,
+            occlusion,
+            occlusion_source: None,
+            occlusion_transform: TextureTransform::default(),
+            occlusion_strength: 1.0,
+            metallic: 0.0,
+            roughness: 1.0,
+            sampler,
+            params,
+            bind_group,
+        };
+        material.update(data);
+        material
+    }
+
+    fn bind_group(
+        data: &GameData,
+        color: &MaterialTexture,
+        occlusion: &MaterialTexture,
+        sampler: &Sampler,
+        params: &Buffer,
+    ) -> BindGroup {
+        data.graphics
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &Self::bind_group_layout(data),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&color.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: params.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(&occlusion.view),
+                    },
+                ],
+            })
+    }
+
+    pub fn with_color_transform(mut self, data: &GameData, transform: TextureTransform) -> Self {
+        self.color_transform = transform;
+        self.update(data);
+        self
+    }
+
+    pub fn with_occlusion_transform(
+        mut self,
+        data: &GameData,
+        transform: TextureTransform,
+    ) -> Self {
+        self.occlusion_transform = transform;
+        self.update(data);
+        self
+    }
+
+    pub fn with_alpha_mode(mut self, data: &GameData, alpha_mode: AlphaMode) -> Self {
+        self.alpha_mode = alpha_mode;
+        self.update(data);
+        self
+    }
+
+    pub fn with_double_sided(mut self, double_sided: bool) -> Self {
+        self.double_sided = double_sided;
+        self
+    }
+
+    /// Sets the ambient occlusion of the material, see [`Self::occlusion`].
+    pub fn with_occlusion(mut self, data: &GameData, image: RgbaImage, strength: f32) -> Self {
+        self.occlusion = MaterialTexture::from_image(data, &image, ColorSpace::Linear);
+        self.occlusion_source = Some(image);
+        self.occlusion_strength = strength;
+        self.bind_group = Self::bind_group(
+            data,
+            &self.color,
+            &self.occlusion,
+            &self.sampler,
+            &self.params,
+        );
+        self.update(data);
+        self
+    }
+
+    /// Reinterprets the color texture, for images that store linear colors
+    /// instead of sRGB. Does nothing without [`Self::source`].
+    pub fn with_color_space(mut self, data: &GameData, color_space: ColorSpace) -> Self {
+        if let Some(source) = &self.source {
+            self.color = MaterialTexture::from_image(data, source, color_space);
+            self.base_color = average_color(source, color_space);
+            self.bind_group = Self::bind_group(
                 data,
-                error_image.as_rgba8().unwrap(),
-                &rhachis::graphics::SamplerType::Nearest,
-            )
-            .unwrap(),
+                &self.color,
+                &self.occlusion,
+                &self.sampler,
+                &self.params,
+            );
+            self.update(data);
         }
+        self
+    }
+
+    pub fn with_metallic_roughness(
+        mut self,
+        data: &GameData,
+        metallic: f32,
+        roughness: f32,
+    ) -> Self {
+        self.metallic = metallic;
+        self.roughness = roughness;
+        self.update(data);
+        self
+    }
+
+    /// Uploads changes to [`Self::color_transform`], [`Self::alpha_mode`],
+    /// [`Self::occlusion_transform`], [`Self::occlusion_strength`],
+    /// [`Self::metallic`] and [`Self::roughness`] to the GPU.
+    pub fn update(&self, data: &GameData) {
+        data.graphics.queue.write_buffer(
+            &self.params,
+            0,
+            bytemuck::bytes_of(&MaterialUniform::new(self)),
+        );
+    }
+
+    pub fn from_path<P: AsRef<Path>>(
+        data: &GameData,
+        path: P,
+        sampler: &SamplerType,
+    ) -> Result<Material, ImageError> {
+        Ok(Self::from_image(
+            data,
+            image::open(path)?.into_rgba8(),
+            sampler,
+        ))
+    }
+
+    /// Makes a material with a single flat linear color.
+    pub fn from_color(data: &GameData, color: [f32; 4]) -> Material {
+        let [r, g, b, a] = color;
+        let image = RgbaImage::from_pixel(
+            1,
+            1,
+            Rgba([
+                ColorSpace::Srgb.encode(r),
+                ColorSpace::Srgb.encode(g),
+                ColorSpace::Srgb.encode(b),
+                ColorSpace::Linear.encode(a),
+            ]),
+        );
+
+        Self::from_image(data, image, &SamplerType::Nearest)
     }
 
     pub fn bind_group_layout(data: &GameData) -> wgpu::BindGroupLayout {
@@ -41,11 +281,229 @@ impl Material {
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
                 ],
             })
     }
 }
 
+/// A texture of a [`Material`], and how its values are stored.
+pub struct MaterialTexture {
+    pub texture: wgpu::Texture,
+    pub view: TextureView,
+    pub color_space: ColorSpace,
+}
+
+impl MaterialTexture {
+    pub fn from_image(data: &GameData, image: &RgbaImage, color_space: ColorSpace) -> Self {
+        let texture = data.graphics.device.create_texture_with_data(
+            &data.graphics.queue,
+            &wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width: image.width().max(1),
+                    height: image.height().max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: color_space.format(),
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            },
+            image.as_raw(),
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self {
+            texture,
+            view,
+            color_space,
+        }
+    }
+}
+
+/// How the values of a texture relate to the light they stand for. Lighting
+/// is done with linear values, so sRGB textures are decoded when sampled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Colors, which most images store as sRGB to give more precision to
+    /// dark values.
+    #[default]
+    Srgb,
+    /// Data that isn't a color, like occlusion, or colors that are already
+    /// linear.
+    Linear,
+}
+
+impl ColorSpace {
+    fn format(self) -> wgpu::TextureFormat {
+        match self {
+            Self::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            Self::Linear => wgpu::TextureFormat::Rgba8Unorm,
+        }
+    }
+
+    /// Turns a stored value into a linear one from 0 to 1.
+    pub fn decode(self, value: u8) -> f32 {
+        let value = value as f32 / 255.0;
+        match self {
+            Self::Srgb if value <= 0.04045 => value / 12.92,
+            Self::Srgb => ((value + 0.055) / 1.055).powf(2.4),
+            Self::Linear => value,
+        }
+    }
+
+    /// Turns a linear value from 0 to 1 into a stored one.
+    pub fn encode(self, value: f32) -> u8 {
+        let value = value.clamp(0.0, 1.0);
+        let value = match self {
+            Self::Srgb if value <= 0.0031308 => value * 12.92,
+            Self::Srgb => 1.055 * value.powf(1.0 / 2.4) - 0.055,
+            Self::Linear => value,
+        };
+        (value * 255.0).round() as u8
+    }
+}
+
+/// Picks the set of texture coordinates a texture uses and moves them, like
+/// the glTF `KHR_texture_transform` extension.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureTransform {
+    pub offset: Vec2,
+    /// Counter-clockwise rotation in radians around the origin.
+    pub rotation: f32,
+    pub scale: Vec2,
+    /// 0 for [`TextureVertex::tex_coords`](crate::model::TextureVertex::tex_coords)
+    /// and 1 for [`TextureVertex::tex_coords_1`](crate::model::TextureVertex::tex_coords_1).
+    pub tex_coord: u32,
+}
+
+impl TextureTransform {
+    /// The top two rows of the 3x3 matrix applying the transform. Offset,
+    /// then rotation, then scale, as in the glTF extension.
+    fn rows(self) -> [[f32; 4]; 2] {
+        let (sin, cos) = self.rotation.sin_cos();
+        [
+            [cos * self.scale.x, sin * self.scale.y, self.offset.x, 0.0],
+            [-sin * self.scale.x, cos * self.scale.y, self.offset.y, 0.0],
+        ]
+    }
+}
+
+impl Default for TextureTransform {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::ZERO,
+            rotation: 0.0,
+            scale: Vec2::ONE,
+            tex_coord: 0,
+        }
+    }
+}
+
+/// How the alpha of a material is used, matching glTF.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AlphaMode {
+    /// The alpha is ignored.
+    #[default]
+    Opaque,
+    /// Fragments with alpha below the cutoff are removed, and the rest are
+    /// opaque.
+    Mask(f32),
+    /// Blended with what is behind it. Blended models are drawn after the
+    /// others, sorted from back to front.
+    Blend,
+}
+
+impl AlphaMode {
+    fn id(self) -> u32 {
+        match self {
+            Self::Opaque => 0,
+            Self::Mask(_) => 1,
+            Self::Blend => 2,
+        }
+    }
+}
+
+/// Averages every pixel after decoding it, since averaging sRGB values
+/// darkens the result.
+fn average_color(image: &RgbaImage, color_space: ColorSpace) -> [f32; 4] {
+    let mut sum = [0.0f64; 4];
+    for pixel in image.pixels() {
+        let [r, g, b, a] = pixel.0;
+        let decoded = [
+            color_space.decode(r),
+            color_space.decode(g),
+            color_space.decode(b),
+            ColorSpace::Linear.decode(a),
+        ];
+        for (sum, value) in sum.iter_mut().zip(decoded) {
+            *sum += value as f64;
+        }
+    }
+    let count = (image.width() as f64 * image.height() as f64).max(1.0);
+    sum.map(|sum| (sum / count) as f32)
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialUniform {
+    /// The top two rows of a 3x3 matrix.
+    uv_transform: [[f32; 4]; 2],
+    /// The average linear color of the color texture.
+    base_color: [f32; 4],
+    occlusion_transform: [[f32; 4]; 2],
+    tex_coord: u32,
+    alpha_mode: u32,
+    alpha_cutoff: f32,
+    occlusion_strength: f32,
+    metallic: f32,
+    roughness: f32,
+    occlusion_tex_coord: u32,
+    _padding: u32,
+}
+
+impl MaterialUniform {
+    fn new(material: &Material) -> Self {
+        Self {
+            uv_transform: material.color_transform.rows(),
+            base_color: material.base_color,
+            occlusion_transform: material.occlusion_transform.rows(),
+            tex_coord: material.color_transform.tex_coord,
+            alpha_mode: material.alpha_mode.id(),
+            alpha_cutoff: match material.alpha_mode {
+                AlphaMode::Mask(cutoff) => cutoff,
+                _ => 0.0,
+            },
+            occlusion_strength: material.occlusion_strength,
+            metallic: material.metallic,
+            roughness: material.roughness,
+            occlusion_tex_coord: material.occlusion_transform.tex_coord,
+            _padding: 0,
+        }
+    }
+}
+
 pub enum MaterialError {
     ImageError(ImageError),
 }
@@ -0,0 +1,39 @@
+struct MaterialUniform {
+    uv_row_0: vec4<f32>,
+    uv_row_1: vec4<f32>,
+    // The average color of the color texture
+    base_color: vec4<f32>,
+    occlusion_row_0: vec4<f32>,
+    occlusion_row_1: vec4<f32>,
+    tex_coord: u32,
+    alpha_mode: u32,
+    alpha_cutoff: f32,
+    occlusion_strength: f32,
+    metallic: f32,
+    roughness: f32,
+    occlusion_tex_coord: u32,
+}
+
+@group(0)@binding(2)
+var<uniform> material: MaterialUniform;
+
+// Picks a set of texture coordinates and transforms it by the top two rows of
+// a 3x3 matrix
+fn transform_uv(in: Vertex, tex_coord: u32, row_0: vec4<f32>, row_1: vec4<f32>) -> vec2<f32> {
+    var uv = in.tex_coords;
+    if tex_coord == 1u {
+        uv = in.tex_coords_1;
+    }
+    let uv_point = vec3<f32>(uv, 1.0);
+    return vec2<f32>(dot(row_0.xyz, uv_point), dot(row_1.xyz, uv_point));
+}
+
+// The coordinates of the color texture, transformed by the material
+fn material_uv(in: Vertex) -> vec2<f32> {
+    return transform_uv(in, material.tex_coord, material.uv_row_0, material.uv_row_1);
+}
+
+// The coordinates of the occlusion texture, transformed by the material
+fn occlusion_uv(in: Vertex) -> vec2<f32> {
+    return transform_uv(in, material.occlusion_tex_coord, material.occlusion_row_0, material.occlusion_row_1);
+}
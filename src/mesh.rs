@@ -0,0 +1,301 @@
+mod simplify;
+
+use std::{collections::HashMap, sync::Arc};
+
+use glam::{Mat3, Mat4, Vec2, Vec3};
+use rhachis::{renderers::Transform, GameData};
+
+use crate::{
+    material::Material,
+    model::{Model, NormalGeneration, TextureVertex},
+    vertex::{VertexAttribute, VertexLayout},
+};
+
+/// Vertices and indices kept on the CPU so they can be edited before being
+/// uploaded as a [`Model`].
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<TextureVertex>,
+    pub indices: Vec<u32>,
+    /// Tangents for each vertex with the handedness of the bitangent in `w`,
+    /// empty until [`Mesh::generate_tangents`] is called.
+    pub tangents: Vec<[f32; 4]>,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<TextureVertex>, indices: Vec<u32>) -> Self {
+        Self {
+            vertices,
+            indices,
+            tangents: vec![],
+        }
+    }
+
+    /// Uploads the mesh, including the tangents if they've been generated.
+    pub fn to_model(
+        &self,
+        data: &GameData,
+        material: Arc<Material>,
+        transforms: Vec<Transform>,
+    ) -> Model {
+        if self.tangents.len() != self.vertices.len() {
+            return Model::new(
+                data,
+                self.vertices.clone(),
+                self.indices.clone(),
+                material,
+                transforms,
+            );
+        }
+
+        // Tangents come after every attribute of `TextureVertex` in the layout
+        let vertices: Vec<u8> = self
+            .vertices
+            .iter()
+            .zip(&self.tangents)
+            .flat_map(|(vertex, tangent)| {
+                bytemuck::bytes_of(vertex)
+                    .iter()
+                    .chain(bytemuck::bytes_of(tangent))
+                    .copied()
+            })
+            .collect();
+        Model::with_layout(
+            data,
+            VertexLayout::TEXTURE_VERTEX.with(VertexAttribute::Tangent),
+            &vertices,
+            self.indices.clone(),
+            material,
+            transforms,
+        )
+    }
+
+    /// Averages the normals of the triangles around each vertex, this
+    /// invalidates tangents.
+    pub fn smooth_normals(&mut self) {
+        NormalGeneration::Smooth.apply(&mut self.vertices, &mut self.indices);
+        self.tangents.clear();
+    }
+
+    /// Gives every triangle its own vertices with the normal of the triangle,
+    /// this invalidates tangents.
+    pub fn flat_normals(&mut self) {
+        NormalGeneration::Flat.apply(&mut self.vertices, &mut self.indices);
+        self.tangents.clear();
+    }
+
+    /// Calculates tangents from the texture coordinates, for use with normal
+    /// maps.
+    pub fn generate_tangents(&mut self) {
+        let mut tangents = vec![Vec3::ZERO; self.vertices.len()];
+        let mut bitangents = vec![Vec3::ZERO; self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| self.vertices[triangle[i] as usize]);
+            let edge_1 = Vec3::from(b.pos) - Vec3::from(a.pos);
+            let edge_2 = Vec3::from(c.pos) - Vec3::from(a.pos);
+            let uv_1 = Vec2::from(b.tex_coords) - Vec2::from(a.tex_coords);
+            let uv_2 = Vec2::from(c.tex_coords) - Vec2::from(a.tex_coords);
+
+            let determinant = uv_1.x * uv_2.y - uv_2.x * uv_1.y;
+            if determinant.abs() < f32::EPSILON {
+                continue;
+            }
+            let tangent = (edge_1 * uv_2.y - edge_2 * uv_1.y) / determinant;
+            let bitangent = (edge_2 * uv_1.x - edge_1 * uv_2.x) / determinant;
+            for &index in triangle {
+                tangents[index as usize] += tangent;
+                bitangents[index as usize] += bitangent;
+            }
+        }
+
+        self.tangents = self
+            .vertices
+            .iter()
+            .zip(tangents)
+            .zip(bitangents)
+            .map(|((vertex, tangent), bitangent)| {
+                let normal = Vec3::from(vertex.normals);
+                // Make the tangent perpendicular to the normal
+                let tangent = (tangent - normal * normal.dot(tangent))
+                    .try_normalize()
+                    .unwrap_or_else(|| normal.any_orthonormal_vector());
+                let handedness = match normal.cross(tangent).dot(bitangent) < 0.0 {
+                    true => -1.0,
+                    false => 1.0,
+                };
+                tangent.extend(handedness).to_array()
+            })
+            .collect();
+    }
+
+    /// The box around every vertex, or `None` if there are no vertices.
+    pub fn bounds(&self) -> Option<Bounds> {
+        let first = Vec3::from(self.vertices.first()?.pos);
+        Some(self.vertices.iter().fold(
+            Bounds {
+                min: first,
+                max: first,
+            },
+            |bounds, vertex| Bounds {
+                min: bounds.min.min(vertex.pos.into()),
+                max: bounds.max.max(vertex.pos.into()),
+            },
+        ))
+    }
+
+    /// Merges vertices whose attributes all round to the same multiple of
+    /// `epsilon`, this invalidates tangents. Vertices closer than `epsilon`
+    /// can still be kept apart if they round to different sides of a
+    /// multiple.
+    pub fn weld(&mut self, epsilon: f32) {
+        let quantize = |value: f32| (value / epsilon.max(f32::EPSILON)).round() as i64;
+
+        let mut unique = HashMap::new();
+        let mut vertices = vec![];
+        let remap: Vec<u32> = self
+            .vertices
+            .iter()
+            .map(|vertex| {
+                let key: Vec<i64> = bytemuck::cast_slice::<_, f32>(std::slice::from_ref(vertex))
+                    .iter()
+                    .map(|value| quantize(*value))
+                    .collect();
+                *unique.entry(key).or_insert_with(|| {
+                    vertices.push(*vertex);
+                    vertices.len() as u32 - 1
+                })
+            })
+            .collect();
+
+        for index in &mut self.indices {
+            *index = remap[*index as usize];
+        }
+        self.vertices = vertices;
+        self.tangents.clear();
+    }
+
+    /// Appends the vertices and triangles of another mesh.
+    pub fn merge(&mut self, other: &Mesh) {
+        let offset = self.vertices.len() as u32;
+        let had_tangents = self.tangents.len() == self.vertices.len();
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices
+            .extend(other.indices.iter().map(|index| index + offset));
+
+        match had_tangents && other.tangents.len() == other.vertices.len() {
+            true => self.tangents.extend_from_slice(&other.tangents),
+            false => self.tangents.clear(),
+        }
+    }
+
+    /// Transforms every vertex, flipping the winding if the transform mirrors
+    /// the mesh so it still faces outwards.
+    pub fn transform(&mut self, matrix: Mat4) {
+        let mirrored = matrix.determinant() < 0.0;
+        let normal_matrix = Mat3::from_mat4(matrix).inverse().transpose();
+        for vertex in &mut self.vertices {
+            vertex.pos = matrix.transform_point3(vertex.pos.into()).into();
+            vertex.normals = (normal_matrix * Vec3::from(vertex.normals))
+                .normalize_or_zero()
+                .into();
+        }
+        for tangent in &mut self.tangents {
+            let direction = matrix.transform_vector3(Vec3::from_slice(tangent));
+            // Mirroring flips which way the bitangent points
+            let handedness = match mirrored {
+                true => -tangent[3],
+                false => tangent[3],
+            };
+            *tangent = direction.normalize_or_zero().extend(handedness).to_array();
+        }
+
+        if mirrored {
+            self.flip_winding();
+        }
+    }
+
+    /// Reverses the order of every triangle, making them face the other way.
+    pub fn flip_winding(&mut self) {
+        for triangle in self.indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+}
+
+impl From<(Vec<TextureVertex>, Vec<u32>)> for Mesh {
+    fn from((vertices, indices): (Vec<TextureVertex>, Vec<u32>)) -> Self {
+        Self::new(vertices, indices)
+    }
+}
+
+/// An axis aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Bounds {
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(pos: [f32; 3]) -> TextureVertex {
+        TextureVertex {
+            pos,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn weld_merges_shared_corners() {
+        // Two triangles of a quad, each with their own copy of the diagonal
+        let mut mesh = Mesh::new(
+            vec![
+                vertex([0.0, 0.0, 0.0]),
+                vertex([1.0, 0.0, 0.0]),
+                vertex([1.0, 1.0, 0.0]),
+                vertex([0.0, 0.0, 0.0001]),
+                vertex([1.0, 1.0, 0.0]),
+                vertex([0.0, 1.0, 0.0]),
+            ],
+            vec![0, 1, 2, 3, 4, 5],
+        );
+        mesh.generate_tangents();
+        mesh.weld(0.001);
+
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3]);
+        assert!(mesh.tangents.is_empty());
+    }
+
+    #[test]
+    fn weld_keeps_distinct_vertices() {
+        let mut mesh = Mesh::new(
+            vec![
+                vertex([0.0, 0.0, 0.0]),
+                vertex([1.0, 0.0, 0.0]),
+                vertex([0.0, 1.0, 0.0]),
+            ],
+            vec![0, 1, 2],
+        );
+        mesh.weld(0.001);
+
+        assert_eq!(mesh.vertices.len(), 3);
+        assert_eq!(mesh.indices, [0, 1, 2]);
+    }
+
+    #[test]
+    fn empty_mesh_has_no_bounds() {
+        assert_eq!(Mesh::default().bounds(), None);
+    }
+}
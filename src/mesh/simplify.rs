@@ -0,0 +1,391 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+use glam::{DVec3, Vec3};
+
+use super::Mesh;
+
+impl Mesh {
+    /// Reduces the number of triangles to around `target_triangles` by
+    /// collapsing the edges that change the shape the least, measured with
+    /// quadric error metrics.
+    ///
+    /// Vertices are never moved or created, so the returned indices can be
+    /// used with the original vertices, allowing levels of detail to share a
+    /// single vertex buffer.
+    pub fn simplify(&self, target_triangles: usize) -> Vec<u32> {
+        Simplifier::new(self).run(target_triangles)
+    }
+}
+
+/// Vertices are grouped by position so texture seams don't split the mesh
+/// apart while it is simplified.
+struct Simplifier {
+    positions: Vec<DVec3>,
+    /// The group of each vertex.
+    vertex_groups: Vec<usize>,
+    /// A vertex of each group, used to replace vertices of collapsed groups.
+    representatives: Vec<u32>,
+    /// The group each group was collapsed into, or itself if it's still alive.
+    parents: Vec<usize>,
+    quadrics: Vec<Quadric>,
+    /// Incremented each time a group changes so outdated collapses can be
+    /// skipped.
+    versions: Vec<u32>,
+    triangles: Vec<[u32; 3]>,
+    alive: Vec<bool>,
+    group_triangles: Vec<Vec<usize>>,
+}
+
+impl Simplifier {
+    fn new(mesh: &Mesh) -> Self {
+        let mut groups = HashMap::new();
+        let mut positions = vec![];
+        let mut representatives = vec![];
+        let vertex_groups = mesh
+            .vertices
+            .iter()
+            .enumerate()
+            .map(|(i, vertex)| {
+                *groups
+                    .entry(vertex.pos.map(f32::to_bits))
+                    .or_insert_with(|| {
+                        positions.push(Vec3::from(vertex.pos).as_dvec3());
+                        representatives.push(i as u32);
+                        positions.len() - 1
+                    })
+            })
+            .collect();
+
+        let triangles: Vec<[u32; 3]> = mesh
+            .indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect();
+
+        let mut simplifier = Self {
+            parents: (0..positions.len()).collect(),
+            quadrics: vec![Quadric::default(); positions.len()],
+            versions: vec![0; positions.len()],
+            group_triangles: vec![vec![]; positions.len()],
+            alive: vec![true; triangles.len()],
+            positions,
+            vertex_groups,
+            representatives,
+            triangles,
+        };
+
+        let mut edge_counts = HashMap::new();
+        for i in 0..simplifier.triangles.len() {
+            let groups = simplifier.triangle_groups(i);
+            if groups[0] == groups[1] || groups[1] == groups[2] || groups[2] == groups[0] {
+                simplifier.alive[i] = false;
+                continue;
+            }
+
+            let [a, b, c] = groups.map(|group| simplifier.positions[group]);
+            let normal = (b - a).cross(c - a);
+            let area = normal.length();
+            if area > f64::EPSILON {
+                let plane = Quadric::plane(normal / area, a, area);
+                for group in groups {
+                    simplifier.quadrics[group].add(&plane);
+                }
+            }
+            for (j, group) in groups.into_iter().enumerate() {
+                simplifier.group_triangles[group].push(i);
+                let next = groups[(j + 1) % 3];
+                *edge_counts
+                    .entry((group.min(next), group.max(next)))
+                    .or_insert(0) += 1;
+            }
+        }
+
+        // Edges only used by one triangle are on the border of the mesh, a
+        // plane perpendicular to the triangle keeps them from moving inwards
+        for i in 0..simplifier.triangles.len() {
+            if !simplifier.alive[i] {
+                continue;
+            }
+            let groups = simplifier.triangle_groups(i);
+            let [a, b, c] = groups.map(|group| simplifier.positions[group]);
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+            for j in 0..3 {
+                let (from, to) = (groups[j], groups[(j + 1) % 3]);
+                if edge_counts[&(from.min(to), from.max(to))] != 1 {
+                    continue;
+                }
+                let edge = simplifier.positions[to] - simplifier.positions[from];
+                let border_normal = edge.cross(normal).normalize_or_zero();
+                let plane = Quadric::plane(
+                    border_normal,
+                    simplifier.positions[from],
+                    edge.length_squared() * 100.0,
+                );
+                simplifier.quadrics[from].add(&plane);
+                simplifier.quadrics[to].add(&plane);
+            }
+        }
+
+        simplifier
+    }
+
+    fn run(mut self, target_triangles: usize) -> Vec<u32> {
+        let mut triangle_count = self.alive.iter().filter(|alive| **alive).count();
+        let mut heap = BinaryHeap::new();
+        for group in 0..self.positions.len() {
+            self.push_collapses(&mut heap, group);
+        }
+
+        while triangle_count > target_triangles {
+            let collapse = match heap.pop() {
+                Some(collapse) => collapse,
+                None => break,
+            };
+            if self.versions[collapse.from] != collapse.from_version
+                || self.versions[collapse.to] != collapse.to_version
+                || self.find(collapse.from) != collapse.from
+                || self.find(collapse.to) != collapse.to
+                || self.flips(collapse.from, collapse.to)
+            {
+                continue;
+            }
+
+            triangle_count -= self.collapse(collapse.from, collapse.to);
+            self.push_collapses(&mut heap, collapse.to);
+        }
+
+        let mut indices = Vec::with_capacity(triangle_count * 3);
+        for (i, triangle) in self.triangles.iter().enumerate() {
+            if !self.alive[i] {
+                continue;
+            }
+            for &vertex in triangle {
+                let group = self.vertex_groups[vertex as usize];
+                let root = self.find(group);
+                indices.push(match root == group {
+                    true => vertex,
+                    false => self.representatives[root],
+                });
+            }
+        }
+        indices
+    }
+
+    fn find(&self, mut group: usize) -> usize {
+        while self.parents[group] != group {
+            group = self.parents[group];
+        }
+        group
+    }
+
+    fn triangle_groups(&self, triangle: usize) -> [usize; 3] {
+        self.triangles[triangle].map(|vertex| self.find(self.vertex_groups[vertex as usize]))
+    }
+
+    /// Adds a possible collapse for every edge around `group`.
+    fn push_collapses(&self, heap: &mut BinaryHeap<Collapse>, group: usize) {
+        let mut neighbours: Vec<usize> = self.group_triangles[group]
+            .iter()
+            .filter(|triangle| self.alive[**triangle])
+            .flat_map(|triangle| self.triangle_groups(*triangle))
+            .filter(|neighbour| *neighbour != group)
+            .collect();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+
+        for neighbour in neighbours {
+            let mut quadric = self.quadrics[group];
+            quadric.add(&self.quadrics[neighbour]);
+            let (from, to) = match quadric.error(self.positions[group])
+                < quadric.error(self.positions[neighbour])
+            {
+                true => (neighbour, group),
+                false => (group, neighbour),
+            };
+            heap.push(Collapse {
+                cost: quadric.error(self.positions[to]),
+                from,
+                to,
+                from_version: self.versions[from],
+                to_version: self.versions[to],
+            });
+        }
+    }
+
+    /// Checks if moving `from` onto `to` would turn any triangles over.
+    fn flips(&self, from: usize, to: usize) -> bool {
+        self.group_triangles[from]
+            .iter()
+            .filter(|triangle| self.alive[**triangle])
+            .any(|&triangle| {
+                let groups = self.triangle_groups(triangle);
+                if groups.contains(&to) {
+                    return false;
+                }
+                let before = groups.map(|group| self.positions[group]);
+                let after = groups.map(|group| match group == from {
+                    true => self.positions[to],
+                    false => self.positions[group],
+                });
+                let normal = |[a, b, c]: [DVec3; 3]| (b - a).cross(c - a);
+                normal(before).dot(normal(after)) <= 0.0
+            })
+    }
+
+    /// Merges `from` into `to`, returning how many triangles were removed.
+    fn collapse(&mut self, from: usize, to: usize) -> usize {
+        self.parents[from] = to;
+        let quadric = self.quadrics[from];
+        self.quadrics[to].add(&quadric);
+        self.versions[from] += 1;
+        self.versions[to] += 1;
+
+        let mut removed = 0;
+        for triangle in std::mem::take(&mut self.group_triangles[from]) {
+            if !self.alive[triangle] {
+                continue;
+            }
+            let [a, b, c] = self.triangle_groups(triangle);
+            if a == b || b == c || c == a {
+                self.alive[triangle] = false;
+                removed += 1;
+            } else {
+                self.group_triangles[to].push(triangle);
+            }
+        }
+        removed
+    }
+}
+
+struct Collapse {
+    cost: f64,
+    from: usize,
+    to: usize,
+    from_version: u32,
+    to_version: u32,
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    /// Reversed so the heap gives the cheapest collapse first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+/// The sum of squared distances to a set of planes, stored as the upper half
+/// of a symmetric 4x4 matrix.
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn plane(normal: DVec3, point: DVec3, weight: f64) -> Self {
+        let [a, b, c] = normal.to_array();
+        let d = -normal.dot(point);
+        Self([
+            a * a,
+            a * b,
+            a * c,
+            a * d,
+            b * b,
+            b * c,
+            b * d,
+            c * c,
+            c * d,
+            d * d,
+        ])
+        .scaled(weight)
+    }
+
+    fn scaled(self, weight: f64) -> Self {
+        Self(self.0.map(|value| value * weight))
+    }
+
+    fn add(&mut self, other: &Self) {
+        for (value, other) in self.0.iter_mut().zip(other.0) {
+            *value += other;
+        }
+    }
+
+    fn error(&self, point: DVec3) -> f64 {
+        let [x, y, z] = point.to_array();
+        let q = &self.0;
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::TextureVertex;
+
+    use super::*;
+
+    /// A flat grid of `size` by `size` quads.
+    fn grid(size: u32) -> Mesh {
+        let vertices = (0..=size)
+            .flat_map(|y| {
+                (0..=size).map(move |x| TextureVertex {
+                    pos: [x as f32, y as f32, 0.0],
+                    normals: [0.0, 0.0, 1.0],
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let indices = (0..size)
+            .flat_map(|y| {
+                (0..size).flat_map(move |x| {
+                    let corner = y * (size + 1) + x;
+                    let above = corner + size + 1;
+                    [corner, corner + 1, above + 1, corner, above + 1, above]
+                })
+            })
+            .collect();
+        Mesh::new(vertices, indices)
+    }
+
+    #[test]
+    fn simplify_reduces_triangles() {
+        let mesh = grid(8);
+        let indices = mesh.simplify(32);
+
+        assert_eq!(indices.len() % 3, 0);
+        assert!(indices.len() / 3 <= 32);
+        assert!(!indices.is_empty());
+        assert!(indices
+            .iter()
+            .all(|&index| (index as usize) < mesh.vertices.len()));
+    }
+
+    #[test]
+    fn simplify_keeps_meshes_under_the_target() {
+        let mesh = grid(2);
+        let indices = mesh.simplify(100);
+
+        assert_eq!(indices.len(), mesh.indices.len());
+    }
+}
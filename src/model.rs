@@ -1,25 +1,62 @@
-use std::{mem::size_of, sync::Arc};
+pub mod shapes;
 
+use std::sync::Arc;
+
+use glam::Vec3;
 use rhachis::{graphics::BufferData, renderers::Transform, GameData};
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
-    Buffer, VertexBufferLayout,
+    Buffer, BufferDescriptor, IndexFormat, RenderPass,
 };
 
-use crate::material::Material;
+use crate::{
+    lod::{Lod, LodInstances},
+    material::Material,
+    mesh::Mesh,
+    vertex::VertexLayout,
+};
 
 pub struct Model {
     pub(crate) vertex_buffer: Buffer,
-    pub indices: BufferData<u16>,
+    pub(crate) vertex_count: u32,
+    pub(crate) layout: VertexLayout,
+    /// Distance from the origin of the model to its furthest vertex.
+    pub(crate) radius: f32,
+    pub indices: BufferData<u32>,
     pub transforms: BufferData<Transform>,
     pub material: Arc<Material>,
+    /// Simplified versions of the model, ordered from most to least detailed.
+    pub lods: Vec<Lod>,
+    pub(crate) lod_instances: Option<LodInstances>,
+    pub cast_shadows: bool,
+    pub receive_shadows: bool,
 }
 
 impl Model {
     pub fn new(
         data: &GameData,
         vertices: Vec<TextureVertex>,
-        indices: Vec<u16>,
+        indices: Vec<u32>,
+        material: Arc<Material>,
+        transforms: Vec<Transform>,
+    ) -> Self {
+        Self::with_layout(
+            data,
+            VertexLayout::TEXTURE_VERTEX,
+            bytemuck::cast_slice(&vertices),
+            indices,
+            material,
+            transforms,
+        )
+    }
+
+    /// Makes a model from vertices with any layout, `vertices` has to be
+    /// packed as described by [`VertexLayout`].
+    pub fn with_layout(
+        data: &GameData,
+        layout: VertexLayout,
+        vertices: &[u8],
+        indices: Vec<u32>,
         material: Arc<Material>,
         transforms: Vec<Transform>,
     ) -> Self {
@@ -28,11 +65,13 @@ impl Model {
             .device
             .create_buffer_init(&BufferInitDescriptor {
                 label: None,
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::VERTEX,
+                contents: vertices,
+                usage: VERTEX_USAGES,
             });
+        let vertex_count = (vertices.len() as u64 / layout.stride()) as u32;
+        let radius = bounding_radius(layout, vertices);
 
-        let indices = BufferData::new(data, indices, wgpu::BufferUsages::INDEX);
+        let indices = BufferData::new(data, indices, INDEX_USAGES);
         let transforms = BufferData::new(
             data,
             transforms,
@@ -41,43 +80,274 @@ impl Model {
 
         Self {
             vertex_buffer,
+            vertex_count,
+            layout,
+            radius,
             indices,
             material,
             transforms,
+            lods: vec![],
+            lod_instances: None,
+            cast_shadows: true,
+            receive_shadows: true,
+        }
+    }
+
+    pub fn layout(&self) -> VertexLayout {
+        self.layout
+    }
+
+    /// Copies the vertices back from the GPU as [`TextureVertex`]es, dropping
+    /// any attributes they can't store. This waits for the GPU to finish so it
+    /// shouldn't be used every frame.
+    pub fn read_vertices(&self, data: &GameData) -> Vec<TextureVertex> {
+        self.layout.texture_vertices(&self.read_vertex_data(data))
+    }
+
+    /// Copies the vertices back from the GPU in the layout of the model.
+    pub fn read_vertex_data(&self, data: &GameData) -> Vec<u8> {
+        let size = self.vertex_count as u64 * self.layout.stride();
+        if size == 0 {
+            return vec![];
+        }
+        let staging = data.graphics.device.create_buffer(&BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = data
+            .graphics
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(&self.vertex_buffer, 0, &staging, 0, size);
+        data.graphics.queue.submit([encoder.finish()]);
+
+        let slice = staging.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| {});
+        data.graphics.device.poll(wgpu::Maintain::Wait);
+        let vertices = slice.get_mapped_range().to_vec();
+        staging.unmap();
+
+        vertices
+    }
+
+    /// Replaces the vertices, the buffer is only reallocated if it needs to
+    /// grow so this can be used every frame.
+    pub fn set_vertices(&mut self, data: &GameData, vertices: &[TextureVertex]) {
+        self.set_vertex_data(
+            data,
+            VertexLayout::TEXTURE_VERTEX,
+            bytemuck::cast_slice(vertices),
+        );
+    }
+
+    /// Replaces the vertices with ones in a different layout. Any [`Lod`]s are
+    /// removed as they were made from the old mesh.
+    pub fn set_vertex_data(&mut self, data: &GameData, layout: VertexLayout, bytes: &[u8]) {
+        if bytes.len() as u64 > self.vertex_buffer.size() {
+            self.vertex_buffer = data.graphics.device.create_buffer(&BufferDescriptor {
+                label: None,
+                size: grown_size(bytes.len()),
+                usage: VERTEX_USAGES,
+                mapped_at_creation: false,
+            });
+        }
+        data.graphics
+            .queue
+            .write_buffer(&self.vertex_buffer, 0, bytes);
+        self.vertex_count = (bytes.len() as u64 / layout.stride()) as u32;
+        self.layout = layout;
+        self.radius = bounding_radius(layout, bytes);
+        self.clear_lods();
+    }
+
+    /// Replaces the indices, the buffer is only reallocated if it needs to
+    /// grow so this can be used every frame. Any [`Lod`]s are removed as they
+    /// were made from the old mesh.
+    pub fn set_indices(&mut self, data: &GameData, indices: Vec<u32>) {
+        let bytes: &[u8] = bytemuck::cast_slice(&indices);
+        if bytes.len() as u64 > self.indices.buffer.size() {
+            self.indices.buffer = data.graphics.device.create_buffer(&BufferDescriptor {
+                label: None,
+                size: grown_size(bytes.len()),
+                usage: INDEX_USAGES,
+                mapped_at_creation: false,
+            });
+        }
+        data.graphics
+            .queue
+            .write_buffer(&self.indices.buffer, 0, bytes);
+        self.indices.buffer_len = indices.len() as u32;
+        self.indices.values = indices;
+        self.clear_lods();
+    }
+
+    fn clear_lods(&mut self) {
+        self.lods.clear();
+        self.lod_instances = None;
+    }
+
+    pub fn set_mesh(&mut self, data: &GameData, mesh: &Mesh) {
+        self.set_vertices(data, &mesh.vertices);
+        self.set_indices(data, mesh.indices.clone());
+    }
+
+    /// The position of each instance, indexed the same way as
+    /// [`Self::draw_instance`].
+    pub(crate) fn instance_positions(&self) -> impl Iterator<Item = Vec3> + '_ {
+        match &self.lod_instances {
+            Some(lod_instances) if !self.lods.is_empty() => &lod_instances.transforms.values,
+            _ => &self.transforms.values,
+        }
+        .iter()
+        .map(|transform| transform.translation)
+    }
+
+    /// Draws a single instance, used when instances have to be sorted.
+    pub(crate) fn draw_instance<'a>(&'a self, render_pass: &mut RenderPass<'a>, instance: u32) {
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        let (transforms, indices) = match &self.lod_instances {
+            Some(lod_instances) if !self.lods.is_empty() => {
+                let level = lod_instances
+                    .ranges
+                    .iter()
+                    .position(|range| range.contains(&instance))
+                    .unwrap_or(0);
+                let indices = match level {
+                    0 => &self.indices,
+                    _ => &self.lods[level - 1].indices,
+                };
+                (&lod_instances.transforms, indices)
+            }
+            _ => (&self.transforms, &self.indices),
+        };
+        render_pass.set_vertex_buffer(1, transforms.buffer.slice(..));
+        render_pass.set_index_buffer(indices.buffer.slice(..), IndexFormat::Uint32);
+        render_pass.draw_indexed(0..indices.buffer_len, 0, instance..instance + 1);
+    }
+
+    pub(crate) fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        match &self.lod_instances {
+            Some(lod_instances) if !self.lods.is_empty() => {
+                render_pass.set_vertex_buffer(1, lod_instances.transforms.buffer.slice(..));
+                for (level, instances) in lod_instances.ranges.iter().enumerate() {
+                    if instances.is_empty() {
+                        continue;
+                    }
+                    let indices = match level {
+                        0 => &self.indices,
+                        _ => &self.lods[level - 1].indices,
+                    };
+                    render_pass.set_index_buffer(indices.buffer.slice(..), IndexFormat::Uint32);
+                    render_pass.draw_indexed(0..indices.buffer_len, 0, instances.clone());
+                }
+            }
+            _ => {
+                render_pass.set_vertex_buffer(1, self.transforms.buffer.slice(..));
+                render_pass.set_index_buffer(self.indices.buffer.slice(..), IndexFormat::Uint32);
+                render_pass.draw_indexed(
+                    0..self.indices.buffer_len,
+                    0,
+                    0..self.transforms.values.len() as u32,
+                );
+            }
         }
     }
 }
 
+fn bounding_radius(layout: VertexLayout, vertices: &[u8]) -> f32 {
+    layout
+        .positions(vertices)
+        .map(Vec3::length)
+        .fold(0.0, f32::max)
+}
+
+const VERTEX_USAGES: wgpu::BufferUsages = wgpu::BufferUsages::VERTEX
+    .union(wgpu::BufferUsages::COPY_SRC)
+    .union(wgpu::BufferUsages::COPY_DST);
+const INDEX_USAGES: wgpu::BufferUsages =
+    wgpu::BufferUsages::INDEX.union(wgpu::BufferUsages::COPY_DST);
+
+/// Leaves room for the buffer to grow more without reallocating every time.
+fn grown_size(len: usize) -> u64 {
+    len.next_power_of_two().max(4) as u64
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct TextureVertex {
     pub pos: [f32; 3],
     pub tex_coords: [f32; 2],
     pub normals: [f32; 3],
+    /// Multiplied with the color of the material, white by default.
+    pub color: [f32; 4],
+    /// A second set of texture coordinates, for textures like lightmaps.
+    pub tex_coords_1: [f32; 2],
+}
+
+impl Default for TextureVertex {
+    fn default() -> Self {
+        Self {
+            pos: [0.0; 3],
+            tex_coords: [0.0; 2],
+            normals: [0.0, 0.0, 1.0],
+            color: [1.0; 4],
+            tex_coords_1: [0.0; 2],
+        }
+    }
 }
 
-impl TextureVertex {
-    pub(crate) fn desc<'a>() -> VertexBufferLayout<'a> {
-        VertexBufferLayout {
-            array_stride: size_of::<Self>() as u64,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: 0,
-                    shader_location: 0,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x2,
-                    offset: size_of::<[f32; 3]>() as u64,
-                    shader_location: 1,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: size_of::<[f32; 5]>() as u64,
-                    shader_location: 6,
-                },
-            ],
+/// How normals are generated for meshes that don't have any.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NormalGeneration {
+    /// Every triangle gets its own vertices facing the same way.
+    Flat,
+    /// Shared vertices average the normals of the triangles around them.
+    #[default]
+    Smooth,
+}
+
+impl NormalGeneration {
+    /// Replaces the normals of the mesh, flat normals need to duplicate
+    /// vertices so both vertices and indices may be changed.
+    pub fn apply(self, vertices: &mut Vec<TextureVertex>, indices: &mut Vec<u32>) {
+        match self {
+            Self::Flat => {
+                let mut flat_vertices = Vec::with_capacity(indices.len());
+                for triangle in indices.chunks_exact(3) {
+                    let mut corners = [0, 1, 2].map(|i| vertices[triangle[i] as usize]);
+                    let normal = triangle_normal(corners.map(|corner| corner.pos));
+                    for corner in &mut corners {
+                        corner.normals = normal;
+                    }
+                    flat_vertices.extend(corners);
+                }
+                *indices = (0..flat_vertices.len() as u32).collect();
+                *vertices = flat_vertices;
+            }
+            Self::Smooth => {
+                let mut normals = vec![Vec3::ZERO; vertices.len()];
+                for triangle in indices.chunks_exact(3) {
+                    let [a, b, c] =
+                        [0, 1, 2].map(|i| Vec3::from(vertices[triangle[i] as usize].pos));
+                    // Not normalized so larger triangles have more influence
+                    let normal = (b - a).cross(c - a);
+                    for &index in triangle {
+                        normals[index as usize] += normal;
+                    }
+                }
+                for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                    vertex.normals = normal.normalize_or_zero().into();
+                }
+            }
         }
     }
 }
+
+fn triangle_normal([a, b, c]: [[f32; 3]; 3]) -> [f32; 3] {
+    let [a, b, c] = [a, b, c].map(Vec3::from);
+    (b - a).cross(c - a).normalize_or_zero().into()
+}
@@ -0,0 +1,285 @@
+//! Generators for simple meshes, all centered on the origin and ready to be
+//! uploaded with [`Mesh::to_model`].
+
+use std::{
+    collections::HashMap,
+    f32::consts::{PI, TAU},
+};
+
+use glam::{Vec2, Vec3};
+
+use super::TextureVertex;
+use crate::mesh::Mesh;
+
+/// A flat plane on the XZ axes facing up.
+pub fn plane(size: Vec2, subdivisions: u32) -> Mesh {
+    let mut mesh = Mesh::default();
+    grid(&mut mesh, subdivisions + 1, subdivisions + 1, |u, v| {
+        (
+            Vec3::new((u - 0.5) * size.x, 0.0, (v - 0.5) * size.y),
+            Vec3::Y,
+        )
+    });
+    mesh
+}
+
+/// A box where every face is split into a grid.
+pub fn cube(size: Vec3, subdivisions: u32) -> Mesh {
+    let mut mesh = Mesh::default();
+    let half = size / 2.0;
+    for (normal, right, up) in [
+        (Vec3::X, Vec3::NEG_Z, Vec3::NEG_Y),
+        (Vec3::NEG_X, Vec3::Z, Vec3::NEG_Y),
+        (Vec3::Y, Vec3::X, Vec3::Z),
+        (Vec3::NEG_Y, Vec3::X, Vec3::NEG_Z),
+        (Vec3::Z, Vec3::X, Vec3::NEG_Y),
+        (Vec3::NEG_Z, Vec3::NEG_X, Vec3::NEG_Y),
+    ] {
+        grid(&mut mesh, subdivisions + 1, subdivisions + 1, |u, v| {
+            let pos = normal + right * (u * 2.0 - 1.0) + up * (v * 2.0 - 1.0);
+            (pos * half, normal)
+        });
+    }
+    mesh
+}
+
+/// A sphere made of rings going from the top to the bottom.
+pub fn uv_sphere(radius: f32, sectors: u32, stacks: u32) -> Mesh {
+    let mut mesh = Mesh::default();
+    grid(&mut mesh, sectors.max(3), stacks.max(2), |u, v| {
+        let normal = sphere_point(u * TAU, v * PI);
+        (normal * radius, normal)
+    });
+    mesh
+}
+
+/// A sphere made by subdividing an icosahedron, which spreads the triangles
+/// more evenly than [`uv_sphere`].
+pub fn icosphere(radius: f32, subdivisions: u32) -> Mesh {
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut points: Vec<Vec3> = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .into_iter()
+    .map(|point| Vec3::from(point).normalize())
+    .collect();
+    let mut triangles = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                points.push(((points[a as usize] + points[b as usize]) / 2.0).normalize());
+                points.len() as u32 - 1
+            })
+        };
+        triangles = triangles
+            .into_iter()
+            .flat_map(|[a, b, c]| {
+                let [ab, bc, ca] = [midpoint(a, b), midpoint(b, c), midpoint(c, a)];
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let vertices = points
+        .into_iter()
+        .map(|normal| TextureVertex {
+            pos: (normal * radius).into(),
+            // Texture coordinates will stretch along the seam
+            tex_coords: [
+                normal.z.atan2(normal.x) / TAU + 0.5,
+                normal.y.clamp(-1.0, 1.0).acos() / PI,
+            ],
+            normals: normal.into(),
+            ..Default::default()
+        })
+        .collect();
+    let mut mesh = Mesh::new(vertices, vec![]);
+    for triangle in triangles {
+        push_triangle(&mut mesh, triangle);
+    }
+    mesh
+}
+
+/// An upright cylinder with flat caps.
+pub fn cylinder(radius: f32, height: f32, sectors: u32, stacks: u32) -> Mesh {
+    let mut mesh = Mesh::default();
+    let sectors = sectors.max(3);
+    grid(&mut mesh, sectors, stacks.max(1), |u, v| {
+        let normal = Vec3::new((u * TAU).cos(), 0.0, (u * TAU).sin());
+        (normal * radius + Vec3::Y * (0.5 - v) * height, normal)
+    });
+    disc(&mut mesh, radius, height / 2.0, sectors, Vec3::Y);
+    disc(&mut mesh, radius, -height / 2.0, sectors, Vec3::NEG_Y);
+    mesh
+}
+
+/// An upright cone with its point at the top.
+pub fn cone(radius: f32, height: f32, sectors: u32, stacks: u32) -> Mesh {
+    let mut mesh = Mesh::default();
+    let sectors = sectors.max(3);
+    grid(&mut mesh, sectors, stacks.max(1), |u, v| {
+        let around = Vec3::new((u * TAU).cos(), 0.0, (u * TAU).sin());
+        // A flat cone faces straight up
+        let normal = (around * height + Vec3::Y * radius)
+            .try_normalize()
+            .unwrap_or(Vec3::Y);
+        (around * radius * v + Vec3::Y * (0.5 - v) * height, normal)
+    });
+    disc(&mut mesh, radius, -height / 2.0, sectors, Vec3::NEG_Y);
+    mesh
+}
+
+/// An upright cylinder with hemispheres on each end, `height` doesn't
+/// include the hemispheres.
+pub fn capsule(radius: f32, height: f32, sectors: u32, rings: u32) -> Mesh {
+    let mut mesh = Mesh::default();
+    let rings = rings.max(1);
+    // Each hemisphere gets `rings` rows with one row between them for the
+    // cylinder
+    let rows = rings * 2 + 1;
+    grid(&mut mesh, sectors.max(3), rows, |u, v| {
+        let row = (v * rows as f32).round() as u32;
+        let (angle, offset) = match row <= rings {
+            true => (row as f32 / rings as f32 * PI / 2.0, height / 2.0),
+            false => ((row - 1) as f32 / rings as f32 * PI / 2.0, -height / 2.0),
+        };
+        let normal = sphere_point(u * TAU, angle);
+        (normal * radius + Vec3::Y * offset, normal)
+    });
+    mesh
+}
+
+/// A ring lying on the XZ axes, `major_radius` is the distance from the
+/// center to the middle of the tube.
+pub fn torus(
+    major_radius: f32,
+    minor_radius: f32,
+    major_segments: u32,
+    minor_segments: u32,
+) -> Mesh {
+    let mut mesh = Mesh::default();
+    grid(
+        &mut mesh,
+        major_segments.max(3),
+        minor_segments.max(3),
+        |u, v| {
+            let around = Vec3::new((u * TAU).cos(), 0.0, (u * TAU).sin());
+            let normal = around * (v * TAU).cos() + Vec3::Y * (v * TAU).sin();
+            (around * major_radius + normal * minor_radius, normal)
+        },
+    );
+    mesh
+}
+
+/// Points on a unit sphere, with `polar` going from the top to the bottom.
+fn sphere_point(azimuth: f32, polar: f32) -> Vec3 {
+    Vec3::new(
+        polar.sin() * azimuth.cos(),
+        polar.cos(),
+        polar.sin() * azimuth.sin(),
+    )
+}
+
+/// Adds a grid of quads, `surface` maps texture coordinates to a position
+/// and normal.
+fn grid(mesh: &mut Mesh, columns: u32, rows: u32, surface: impl Fn(f32, f32) -> (Vec3, Vec3)) {
+    let start = mesh.vertices.len() as u32;
+    for row in 0..=rows {
+        for column in 0..=columns {
+            let u = column as f32 / columns as f32;
+            let v = row as f32 / rows as f32;
+            let (pos, normal) = surface(u, v);
+            mesh.vertices.push(TextureVertex {
+                pos: pos.into(),
+                tex_coords: [u, v],
+                normals: normal.into(),
+                ..Default::default()
+            });
+        }
+    }
+
+    let index = |column: u32, row: u32| start + row * (columns + 1) + column;
+    for row in 0..rows {
+        for column in 0..columns {
+            let [a, b, c, d] = [
+                index(column, row),
+                index(column + 1, row),
+                index(column, row + 1),
+                index(column + 1, row + 1),
+            ];
+            push_triangle(mesh, [a, b, c]);
+            push_triangle(mesh, [b, d, c]);
+        }
+    }
+}
+
+/// Adds a flat circle at the height `y`.
+fn disc(mesh: &mut Mesh, radius: f32, y: f32, sectors: u32, normal: Vec3) {
+    let center = mesh.vertices.len() as u32;
+    mesh.vertices.push(TextureVertex {
+        pos: [0.0, y, 0.0],
+        tex_coords: [0.5, 0.5],
+        normals: normal.into(),
+        ..Default::default()
+    });
+    for sector in 0..=sectors {
+        let (sin, cos) = (sector as f32 / sectors as f32 * TAU).sin_cos();
+        mesh.vertices.push(TextureVertex {
+            pos: [cos * radius, y, sin * radius],
+            tex_coords: [cos * 0.5 + 0.5, sin * 0.5 + 0.5],
+            normals: normal.into(),
+            ..Default::default()
+        });
+    }
+    for sector in 0..sectors {
+        push_triangle(mesh, [center, center + sector + 1, center + sector + 2]);
+    }
+}
+
+/// Adds a triangle, flipping it if needed so it winds counter-clockwise when
+/// looking at it from the side its normals point to.
+fn push_triangle(mesh: &mut Mesh, [a, b, c]: [u32; 3]) {
+    let [pa, pb, pc] = [a, b, c].map(|index| Vec3::from(mesh.vertices[index as usize].pos));
+    let normal: Vec3 = [a, b, c]
+        .map(|index| Vec3::from(mesh.vertices[index as usize].normals))
+        .into_iter()
+        .sum();
+    match (pb - pa).cross(pc - pa).dot(normal) < 0.0 {
+        true => mesh.indices.extend([a, c, b]),
+        false => mesh.indices.extend([a, b, c]),
+    }
+}
@@ -0,0 +1,123 @@
+use std::{path::Path, sync::Arc};
+
+use rhachis::{graphics::SamplerType, renderers::Transform, GameData};
+
+use crate::{
+    material::{AlphaMode, Material},
+    model::{Model, NormalGeneration, TextureVertex},
+    Renderer,
+};
+
+impl Renderer {
+    /// Loads every object in a Wavefront OBJ file along with the materials
+    /// from its MTL file, returning the ids of the new models. Textures are
+    /// found relative to the OBJ file and `normals` is used for objects that
+    /// don't have their own normals.
+    pub fn load_obj<P: AsRef<Path>>(
+        &mut self,
+        data: &GameData,
+        path: P,
+        normals: NormalGeneration,
+    ) -> Result<Vec<usize>, tobj::LoadError> {
+        let path = path.as_ref();
+        let (objects, materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let materials: Vec<_> = materials
+            .unwrap_or_default()
+            .iter()
+            .map(|material| Arc::new(obj_material(data, directory, material)))
+            .collect();
+        let default_material = Arc::new(Material::from_color(data, [1.0; 4]));
+
+        let models = objects
+            .into_iter()
+            .map(|object| {
+                let mesh = object.mesh;
+                if mesh
+                    .indices
+                    .iter()
+                    .any(|&index| index as usize * 3 >= mesh.positions.len())
+                {
+                    return Err(tobj::LoadError::FaceVertexOutOfBounds);
+                }
+
+                let mut vertices: Vec<_> = mesh
+                    .positions
+                    .chunks_exact(3)
+                    .enumerate()
+                    .map(|(i, pos)| TextureVertex {
+                        pos: [pos[0], pos[1], pos[2]],
+                        // OBJ texture coordinates start at the bottom
+                        tex_coords: mesh
+                            .texcoords
+                            .get(i * 2..i * 2 + 2)
+                            .map(|uv| [uv[0], 1.0 - uv[1]])
+                            .unwrap_or_default(),
+                        normals: mesh
+                            .normals
+                            .get(i * 3..i * 3 + 3)
+                            .map(|normal| [normal[0], normal[1], normal[2]])
+                            .unwrap_or_default(),
+                        color: mesh
+                            .vertex_color
+                            .get(i * 3..i * 3 + 3)
+                            .map(|color| [color[0], color[1], color[2], 1.0])
+                            .unwrap_or([1.0; 4]),
+                        ..Default::default()
+                    })
+                    .collect();
+                let mut indices = mesh.indices.clone();
+                if mesh.normals.is_empty() {
+                    normals.apply(&mut vertices, &mut indices);
+                }
+
+                let material = mesh
+                    .material_id
+                    .and_then(|id| materials.get(id))
+                    .unwrap_or(&default_material)
+                    .clone();
+
+                Ok(Model::new(
+                    data,
+                    vertices,
+                    indices,
+                    material,
+                    vec![Transform::default()],
+                ))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(self.models.append(models))
+    }
+
+    pub fn with_obj<P: AsRef<Path>>(
+        mut self,
+        data: &GameData,
+        path: P,
+        normals: NormalGeneration,
+    ) -> Self {
+        self.load_obj(data, path, normals).unwrap();
+        self
+    }
+}
+
+/// Uses the diffuse texture of an MTL material if it can be loaded, otherwise
+/// the diffuse color. Materials that are partly dissolved are blended.
+fn obj_material(data: &GameData, directory: &Path, material: &tobj::Material) -> Material {
+    let converted = material
+        .diffuse_texture
+        .as_ref()
+        .and_then(|texture| {
+            Material::from_path(data, directory.join(texture), &SamplerType::Linear).ok()
+        })
+        .unwrap_or_else(|| {
+            let [r, g, b] = material.diffuse.unwrap_or([1.0; 3]);
+            Material::from_color(data, [r, g, b, material.dissolve.unwrap_or(1.0)])
+        });
+
+    match material.dissolve {
+        Some(dissolve) if dissolve < 1.0 => converted.with_alpha_mode(data, AlphaMode::Blend),
+        _ => converted,
+    }
+}
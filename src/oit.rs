@@ -0,0 +1,146 @@
+use glam::UVec2;
+use rhachis::GameData;
+use wgpu::{BindGroup, RenderPass, RenderPipeline, TextureView};
+
+use crate::tonemap::HDR_FORMAT;
+
+pub(crate) const ACCUM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+pub(crate) const REVEALAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
+
+/// Targets for weighted blended order independent transparency, and the
+/// pipeline that composites them over the opaque models.
+pub(crate) struct Oit {
+    pub accum: TextureView,
+    pub revealage: TextureView,
+    bind_group: BindGroup,
+    pipeline: RenderPipeline,
+}
+
+impl Oit {
+    pub fn new(data: &GameData, size: UVec2) -> Self {
+        let shader = data
+            .graphics
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("composite.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("composite.wgsl").into()),
+            });
+
+        let pipeline_layout =
+            data.graphics
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[&Self::bind_group_layout(data)],
+                    push_constant_ranges: &[],
+                });
+
+        let pipeline =
+            data.graphics
+                .device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("composite_pipeline"),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "composite_vertex",
+                        buffers: &[],
+                    },
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    fragment: Some(wgpu::FragmentState {
+                        entry_point: "composite_fragment",
+                        module: &shader,
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: HDR_FORMAT,
+                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    multiview: None,
+                });
+
+        let (accum, revealage, bind_group) = Self::targets(data, size);
+        Self {
+            accum,
+            revealage,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    pub fn resize(&mut self, data: &GameData, size: UVec2) {
+        (self.accum, self.revealage, self.bind_group) = Self::targets(data, size);
+    }
+
+    /// Blends the average of the transparent fragments over the screen.
+    pub fn composite<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn targets(data: &GameData, size: UVec2) -> (TextureView, TextureView, BindGroup) {
+        let target = |format| {
+            data.graphics
+                .device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: None,
+                    size: wgpu::Extent3d {
+                        width: size.x.max(1),
+                        height: size.y.max(1),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let accum = target(ACCUM_FORMAT);
+        let revealage = target(REVEALAGE_FORMAT);
+
+        let bind_group = data
+            .graphics
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &Self::bind_group_layout(data),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&accum),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&revealage),
+                    },
+                ],
+            });
+
+        (accum, revealage, bind_group)
+    }
+
+    fn bind_group_layout(data: &GameData) -> wgpu::BindGroupLayout {
+        let entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        data.graphics
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[entry(0), entry(1)],
+            })
+    }
+}
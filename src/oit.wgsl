@@ -0,0 +1,16 @@
+struct OitOutput {
+    @location(0) accum: vec4<f32>,
+    @location(1) revealage: f32,
+}
+
+// Weights fragments by their distance from the camera so closer ones count for
+// more, using equation 7 of "Weighted Blended Order-Independent Transparency"
+// by McGuire and Bavoil
+fn weighted_oit(color: vec4<f32>, view_depth: f32) -> OitOutput {
+    let z = abs(view_depth);
+    let weight = color.a * clamp(10.0 / (0.00001 + pow(z / 5.0, 2.0) + pow(z / 200.0, 6.0)), 0.01, 3000.0);
+    var output: OitOutput;
+    output.accum = vec4<f32>(color.rgb * color.a, color.a) * weight;
+    output.revealage = color.a;
+    return output;
+}
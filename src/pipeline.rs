@@ -0,0 +1,229 @@
+use rhachis::{graphics::Bindable, renderers::Transform, GameData};
+use wgpu::RenderPipeline;
+
+use crate::{
+    cluster::Clusters,
+    light::LightUniform,
+    material::{AlphaMode, Material},
+    model::Model,
+    oit::{ACCUM_FORMAT, REVEALAGE_FORMAT},
+    tonemap::HDR_FORMAT,
+    vertex::VertexLayout,
+    Pipeline,
+};
+
+/// Everything that needs a different variant of a pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct PipelineKey {
+    pub pipeline: Pipeline,
+    pub layout: VertexLayout,
+    pub blending: Blending,
+    pub double_sided: bool,
+    pub receive_shadows: bool,
+}
+
+/// How a pipeline writes its color, blended pipelines don't write to the
+/// depth buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Blending {
+    Opaque,
+    Alpha,
+    /// Writes to the targets of [`Oit`](crate::oit::Oit) instead of the
+    /// screen.
+    WeightedOit,
+}
+
+impl PipelineKey {
+    pub fn new(pipeline: Pipeline, model: &Model, oit: bool) -> Self {
+        Self {
+            pipeline,
+            layout: model.layout,
+            blending: match (model.material.alpha_mode, oit) {
+                (AlphaMode::Blend, false) => Blending::Alpha,
+                (AlphaMode::Blend, true) => Blending::WeightedOit,
+                _ => Blending::Opaque,
+            },
+            double_sided: model.material.double_sided,
+            receive_shadows: model.receive_shadows,
+        }
+    }
+
+    /// Compiles the variant of the pipeline.
+    pub fn create(self, data: &GameData) -> RenderPipeline {
+        let Self {
+            pipeline,
+            layout,
+            blending,
+            double_sided,
+            receive_shadows,
+        } = self;
+        let (label, source, vertex_entry, fragment_entry) = match pipeline {
+            Pipeline::Normal => (
+                "shader.wgsl",
+                include_str!("shader.wgsl"),
+                "vertex_main",
+                "fragment_main",
+            ),
+            Pipeline::LightCount => (
+                "shader.wgsl",
+                include_str!("shader.wgsl"),
+                "vertex_main",
+                "light_count_fragment",
+            ),
+            Pipeline::Texture | Pipeline::Wireframe => (
+                "debug.wgsl",
+                include_str!("debug.wgsl"),
+                "texture_vertex",
+                "texture_fragment",
+            ),
+            Pipeline::Color => (
+                "debug.wgsl",
+                include_str!("debug.wgsl"),
+                "texture_vertex",
+                "color_fragment",
+            ),
+        };
+
+        let shader = data
+            .graphics
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(label),
+                source: wgpu::ShaderSource::Wgsl(
+                    format!(
+                        "let RECEIVE_SHADOWS: bool = {receive_shadows};\n\n{}{}\n{}\n{source}",
+                        layout.shader_header(),
+                        include_str!("material.wgsl"),
+                        include_str!("oit.wgsl"),
+                    )
+                    .into(),
+                ),
+            });
+
+        let material_layout = Material::bind_group_layout(data);
+        let camera_layout = Transform::bind_group_layout(data);
+        let lights_layout = LightUniform::bind_group_layout(data);
+        let clusters_layout = Clusters::bind_group_layout(data);
+        let bind_group_layouts = match pipeline.lit() {
+            true => vec![
+                &material_layout,
+                &camera_layout,
+                &lights_layout,
+                &clusters_layout,
+            ],
+            false => vec![&material_layout, &camera_layout],
+        };
+        let pipeline_layout =
+            data.graphics
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &bind_group_layouts,
+                    push_constant_ranges: &[],
+                });
+
+        let (fragment_entry, targets) = match blending {
+            Blending::Opaque | Blending::Alpha => (
+                fragment_entry.to_owned(),
+                vec![Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: match blending {
+                        Blending::Alpha => Some(wgpu::BlendState::ALPHA_BLENDING),
+                        _ => None,
+                    },
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            ),
+            // Colors are summed, and the revealage is multiplied by one minus
+            // the alpha of each fragment
+            Blending::WeightedOit => (
+                format!("{fragment_entry}_oit"),
+                vec![
+                    Some(wgpu::ColorTargetState {
+                        format: ACCUM_FORMAT,
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::One,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                            alpha: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::One,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: REVEALAGE_FORMAT,
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::Zero,
+                                dst_factor: wgpu::BlendFactor::OneMinusSrc,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                            alpha: wgpu::BlendComponent::REPLACE,
+                        }),
+                        write_mask: wgpu::ColorWrites::RED,
+                    }),
+                ],
+            ),
+        };
+
+        let attributes = layout.wgpu_attributes();
+        let vertex_layout = wgpu::VertexBufferLayout {
+            array_stride: layout.stride(),
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &attributes,
+        };
+
+        data.graphics
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(&format!("{pipeline:?} pipeline")),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: vertex_entry,
+                    buffers: &[vertex_layout, Transform::desc()],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: match double_sided || pipeline == Pipeline::Wireframe {
+                        true => None,
+                        false => Some(wgpu::Face::Back),
+                    },
+                    unclipped_depth: false,
+                    polygon_mode: match pipeline {
+                        Pipeline::Wireframe => wgpu::PolygonMode::Line,
+                        _ => wgpu::PolygonMode::Fill,
+                    },
+                    conservative: false,
+                },
+                depth_stencil: match pipeline {
+                    Pipeline::Wireframe => None,
+                    _ => Some(wgpu::DepthStencilState {
+                        format: wgpu::TextureFormat::Depth32Float,
+                        depth_write_enabled: blending == Blending::Opaque,
+                        depth_compare: wgpu::CompareFunction::Less,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                },
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    entry_point: &fragment_entry,
+                    module: &shader,
+                    targets: &targets,
+                }),
+                multiview: None,
+            })
+    }
+}
@@ -0,0 +1,432 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    sync::Arc,
+};
+
+use rhachis::{renderers::Transform, GameData};
+
+use crate::{
+    material::Material,
+    model::{Model, NormalGeneration, TextureVertex},
+    Renderer,
+};
+
+impl Renderer {
+    /// Loads an ASCII or binary PLY file as a single model, returning its id.
+    /// Vertex colors are kept and `normals` is used if the file has no
+    /// normals of its own.
+    pub fn load_ply<P: AsRef<Path>>(
+        &mut self,
+        data: &GameData,
+        path: P,
+        normals: NormalGeneration,
+        material: Arc<Material>,
+    ) -> io::Result<usize> {
+        let ply = Ply::read(BufReader::new(File::open(path)?))?;
+
+        let mut vertices = ply.vertices;
+        let mut indices = ply.indices;
+        if !ply.has_normals {
+            normals.apply(&mut vertices, &mut indices);
+        }
+
+        Ok(self.models.push(Model::new(
+            data,
+            vertices,
+            indices,
+            material,
+            vec![Transform::default()],
+        )))
+    }
+}
+
+struct Ply {
+    vertices: Vec<TextureVertex>,
+    indices: Vec<u32>,
+    has_normals: bool,
+}
+
+impl Ply {
+    fn read<R: BufRead>(mut reader: R) -> io::Result<Self> {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if line.trim_end() != "ply" {
+            return Err(invalid_data("missing ply magic number"));
+        }
+
+        let mut format = None;
+        let mut elements: Vec<Element> = vec![];
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(invalid_data("header has no end"));
+            }
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("format") => {
+                    format = Some(match words.next() {
+                        Some("ascii") => Format::Ascii,
+                        Some("binary_little_endian") => Format::BinaryLittleEndian,
+                        Some("binary_big_endian") => Format::BinaryBigEndian,
+                        _ => return Err(invalid_data("unknown format")),
+                    });
+                }
+                Some("element") => {
+                    let name = words.next().unwrap_or_default().to_owned();
+                    let count = words
+                        .next()
+                        .and_then(|count| count.parse().ok())
+                        .ok_or_else(|| invalid_data("element has no count"))?;
+                    elements.push(Element {
+                        name,
+                        count,
+                        properties: vec![],
+                    });
+                }
+                Some("property") => {
+                    let ty = match words.next() {
+                        Some("list") => PropertyType::List {
+                            count: Scalar::parse(words.next())?,
+                            item: Scalar::parse(words.next())?,
+                        },
+                        ty => PropertyType::Scalar(Scalar::parse(ty)?),
+                    };
+                    let name = words.next().unwrap_or_default().to_owned();
+                    elements
+                        .last_mut()
+                        .ok_or_else(|| invalid_data("property outside of an element"))?
+                        .properties
+                        .push(Property { name, ty });
+                }
+                Some("end_header") => break,
+                _ => {}
+            }
+        }
+
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        let text;
+        let mut body = match format.ok_or_else(|| invalid_data("missing format"))? {
+            Format::Ascii => {
+                text = String::from_utf8(bytes).map_err(|_| invalid_data("invalid text"))?;
+                Body::Ascii(text.split_ascii_whitespace())
+            }
+            Format::BinaryLittleEndian => Body::Binary {
+                bytes: &bytes,
+                big_endian: false,
+            },
+            Format::BinaryBigEndian => Body::Binary {
+                bytes: &bytes,
+                big_endian: true,
+            },
+        };
+
+        let mut ply = Ply {
+            vertices: vec![],
+            indices: vec![],
+            has_normals: false,
+        };
+        for element in &elements {
+            match element.name.as_str() {
+                "vertex" => ply.read_vertices(element, &mut body)?,
+                "face" => ply.read_faces(element, &mut body)?,
+                _ => {
+                    for _ in 0..element.count {
+                        for property in &element.properties {
+                            property.skip(&mut body)?;
+                        }
+                    }
+                }
+            }
+        }
+        // Faces can come before the vertices they use, so they're checked last
+        if ply
+            .indices
+            .iter()
+            .any(|&index| index as usize >= ply.vertices.len())
+        {
+            return Err(invalid_data("vertex index out of range"));
+        }
+
+        Ok(ply)
+    }
+
+    fn read_vertices(&mut self, element: &Element, body: &mut Body) -> io::Result<()> {
+        let find = |names: &[&str]| {
+            element
+                .properties
+                .iter()
+                .position(|property| names.contains(&property.name.as_str()))
+        };
+        let pos = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let normals = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+        let tex_coords = [
+            find(&["s", "u", "texture_s", "texture_u"]),
+            find(&["t", "v", "texture_t", "texture_v"]),
+        ];
+        let color = [
+            find(&["red"]),
+            find(&["green"]),
+            find(&["blue"]),
+            find(&["alpha"]),
+        ];
+        self.has_normals = normals.iter().all(Option::is_some);
+
+        let mut values = vec![0.0; element.properties.len()];
+        for _ in 0..element.count {
+            for (value, property) in values.iter_mut().zip(&element.properties) {
+                *value = match property.ty {
+                    PropertyType::Scalar(scalar) => body.read(scalar)?,
+                    PropertyType::List { .. } => {
+                        property.skip(body)?;
+                        0.0
+                    }
+                };
+            }
+            let get = |index: Option<usize>, default: f32| {
+                index.map(|index| values[index] as f32).unwrap_or(default)
+            };
+            // Integer colors use the full range of their type
+            let get_color = |index: Option<usize>| {
+                index
+                    .map(|index| match element.properties[index].ty {
+                        PropertyType::Scalar(Scalar::U8) => values[index] as f32 / 255.0,
+                        PropertyType::Scalar(Scalar::U16) => values[index] as f32 / 65535.0,
+                        _ => values[index] as f32,
+                    })
+                    .unwrap_or(1.0)
+            };
+
+            self.vertices.push(TextureVertex {
+                pos: pos.map(|index| get(index, 0.0)),
+                // PLY texture coordinates start at the bottom
+                tex_coords: [get(tex_coords[0], 0.0), 1.0 - get(tex_coords[1], 0.0)],
+                normals: normals.map(|index| get(index, 0.0)),
+                color: color.map(get_color),
+                ..Default::default()
+            });
+        }
+
+        Ok(())
+    }
+
+    fn read_faces(&mut self, element: &Element, body: &mut Body) -> io::Result<()> {
+        let indices_property = element.properties.iter().position(|property| {
+            property.name == "vertex_indices" || property.name == "vertex_index"
+        });
+
+        for _ in 0..element.count {
+            for (i, property) in element.properties.iter().enumerate() {
+                match (property.ty, Some(i) == indices_property) {
+                    (PropertyType::List { count, item }, true) => {
+                        let count = body.read(count)? as usize;
+                        let mut polygon = Vec::with_capacity(count);
+                        for _ in 0..count {
+                            let index = body.read(item)?;
+                            if index < 0.0 {
+                                return Err(invalid_data("negative vertex index"));
+                            }
+                            polygon.push(index as u32);
+                        }
+                        // Polygons are split into a fan of triangles
+                        for i in 2..polygon.len() {
+                            self.indices
+                                .extend([polygon[0], polygon[i - 1], polygon[i]]);
+                        }
+                    }
+                    _ => property.skip(body)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Property {
+    name: String,
+    ty: PropertyType,
+}
+
+impl Property {
+    fn skip(&self, body: &mut Body) -> io::Result<()> {
+        match self.ty {
+            PropertyType::Scalar(scalar) => {
+                body.read(scalar)?;
+            }
+            PropertyType::List { count, item } => {
+                for _ in 0..body.read(count)? as usize {
+                    body.read(item)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum PropertyType {
+    Scalar(Scalar),
+    List { count: Scalar, item: Scalar },
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: Option<&str>) -> io::Result<Self> {
+        Ok(match name {
+            Some("char" | "int8") => Self::I8,
+            Some("uchar" | "uint8") => Self::U8,
+            Some("short" | "int16") => Self::I16,
+            Some("ushort" | "uint16") => Self::U16,
+            Some("int" | "int32") => Self::I32,
+            Some("uint" | "uint32") => Self::U32,
+            Some("float" | "float32") => Self::F32,
+            Some("double" | "float64") => Self::F64,
+            _ => return Err(invalid_data("unknown property type")),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+}
+
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl Body<'_> {
+    fn read(&mut self, scalar: Scalar) -> io::Result<f64> {
+        match self {
+            Self::Ascii(words) => words
+                .next()
+                .and_then(|word| word.parse().ok())
+                .ok_or_else(|| invalid_data("missing or invalid value")),
+            Self::Binary { bytes, big_endian } => {
+                if bytes.len() < scalar.size() {
+                    return Err(invalid_data("unexpected end of file"));
+                }
+                let (value, rest) = bytes.split_at(scalar.size());
+                *bytes = rest;
+
+                macro_rules! from_bytes {
+                    ($ty:ty) => {{
+                        let value = value.try_into().unwrap();
+                        match big_endian {
+                            true => <$ty>::from_be_bytes(value) as f64,
+                            false => <$ty>::from_le_bytes(value) as f64,
+                        }
+                    }};
+                }
+                Ok(match scalar {
+                    Scalar::I8 => from_bytes!(i8),
+                    Scalar::U8 => from_bytes!(u8),
+                    Scalar::I16 => from_bytes!(i16),
+                    Scalar::U16 => from_bytes!(u16),
+                    Scalar::I32 => from_bytes!(i32),
+                    Scalar::U32 => from_bytes!(u32),
+                    Scalar::F32 => from_bytes!(f32),
+                    Scalar::F64 => from_bytes!(f64),
+                })
+            }
+        }
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid PLY file: {message}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "ply
+format ascii 1.0
+element vertex 3
+property float x
+property float y
+property float z
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0
+1 0 0
+0 1 0
+";
+
+    fn read(text: &str) -> io::Result<Ply> {
+        Ply::read(text.as_bytes())
+    }
+
+    #[test]
+    fn reads_triangle() {
+        let ply = read(&format!("{HEADER}3 0 1 2\n")).unwrap();
+
+        assert_eq!(ply.vertices.len(), 3);
+        assert_eq!(ply.indices, [0, 1, 2]);
+        assert!(!ply.has_normals);
+    }
+
+    #[test]
+    fn rejects_index_out_of_range() {
+        let error = read(&format!("{HEADER}3 0 1 3\n")).err().unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_negative_index() {
+        let error = read(&format!("{HEADER}3 0 1 -1\n")).err().unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_truncated_body() {
+        let error = read(&format!("{HEADER}3 0 1\n")).err().unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_missing_magic_number() {
+        let error = read("format ascii 1.0\nend_header\n").err().unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
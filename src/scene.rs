@@ -0,0 +1,430 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use glam::{Mat4, Vec2};
+use gltf::{image::Format, texture::MagFilter};
+use image::RgbaImage;
+use rhachis::{graphics::SamplerType, renderers::Transform, GameData};
+
+use crate::{
+    camera::Camera,
+    light::Light,
+    material::{AlphaMode, ColorSpace, Material, TextureTransform},
+    model::{Model, TextureVertex},
+    Renderer,
+};
+
+/// Everything that was added to a [`Renderer`] when loading a glTF scene.
+#[derive(Default)]
+pub struct LoadedScene {
+    pub name: Option<String>,
+    /// Ids of the models in [`Renderer::models`].
+    pub models: Vec<usize>,
+    pub materials: Vec<Arc<Material>>,
+    pub nodes: Vec<LoadedNode>,
+    /// Indices into [`Renderer::cameras`].
+    pub cameras: Vec<usize>,
+    /// Indices into [`Renderer::lights`].
+    pub lights: Vec<usize>,
+    /// Names of the animations that affect nodes in this scene.
+    pub animations: Vec<String>,
+}
+
+impl LoadedScene {
+    /// Finds the first node with a matching name.
+    pub fn node(&self, name: &str) -> Option<&LoadedNode> {
+        self.nodes
+            .iter()
+            .find(|node| node.name.as_deref() == Some(name))
+    }
+}
+
+pub struct LoadedNode {
+    pub name: Option<String>,
+    /// The world space transform of the node.
+    pub transform: Mat4,
+    /// Ids of the models in [`Renderer::models`], one for each primitive of
+    /// the node's mesh.
+    pub models: Vec<usize>,
+    /// Index into [`Renderer::cameras`].
+    pub camera: Option<usize>,
+    /// Index into [`Renderer::lights`].
+    pub light: Option<usize>,
+    /// Indices into [`LoadedScene::nodes`].
+    pub children: Vec<usize>,
+}
+
+struct GltfImport {
+    buffers: Vec<gltf::buffer::Data>,
+    images: Vec<gltf::image::Data>,
+    materials: HashMap<Option<usize>, Arc<Material>>,
+}
+
+impl Renderer {
+    pub fn load_gltf<P: AsRef<Path>>(
+        &mut self,
+        data: &GameData,
+        path: P,
+        scene: usize,
+    ) -> Vec<usize> {
+        let (document, mut import) = GltfImport::new(path).unwrap();
+        let scene = document.scenes().nth(scene).unwrap();
+        self.load_gltf_scene(data, &document, &mut import, &scene)
+            .models
+    }
+
+    /// Loads every scene in a glTF file, `.glb` files and embedded data URIs
+    /// are supported and other files are found relative to the glTF file.
+    pub fn load_gltf_scenes<P: AsRef<Path>>(
+        &mut self,
+        data: &GameData,
+        path: P,
+    ) -> Result<Vec<LoadedScene>, gltf::Error> {
+        let (document, mut import) = GltfImport::new(path)?;
+        Ok(document
+            .scenes()
+            .map(|scene| self.load_gltf_scene(data, &document, &mut import, &scene))
+            .collect())
+    }
+
+    /// Loads every scene in a glTF or GLB file that is already in memory,
+    /// such as one from [`include_bytes`]. All of the buffers and images must
+    /// be embedded since there is no path to find other files relative to.
+    pub fn load_gltf_scenes_from_slice(
+        &mut self,
+        data: &GameData,
+        bytes: &[u8],
+    ) -> Result<Vec<LoadedScene>, gltf::Error> {
+        let (document, mut import) = GltfImport::from_slice(bytes)?;
+        Ok(document
+            .scenes()
+            .map(|scene| self.load_gltf_scene(data, &document, &mut import, &scene))
+            .collect())
+    }
+
+    /// Loads the first scene in a glTF file called `name`, returning `None`
+    /// if there is no such scene.
+    pub fn load_gltf_scene_by_name<P: AsRef<Path>>(
+        &mut self,
+        data: &GameData,
+        path: P,
+        name: &str,
+    ) -> Result<Option<LoadedScene>, gltf::Error> {
+        let (document, mut import) = GltfImport::new(path)?;
+        let scene = document.scenes().find(|scene| scene.name() == Some(name));
+        Ok(scene.map(|scene| self.load_gltf_scene(data, &document, &mut import, &scene)))
+    }
+
+    fn load_gltf_scene(
+        &mut self,
+        data: &GameData,
+        document: &gltf::Document,
+        import: &mut GltfImport,
+        scene: &gltf::Scene,
+    ) -> LoadedScene {
+        let mut loaded = LoadedScene {
+            name: scene.name().map(str::to_owned),
+            ..Default::default()
+        };
+
+        let lights_before = self.lights.values.len();
+        let mut scene_nodes = vec![];
+        for node in scene.nodes() {
+            self.load_gltf_node(
+                data,
+                import,
+                &node,
+                Mat4::IDENTITY,
+                &mut loaded,
+                &mut scene_nodes,
+            );
+        }
+        if self.lights.values.len() != lights_before {
+            self.update_lights(data);
+        }
+
+        loaded.animations = document
+            .animations()
+            .filter(|animation| {
+                animation
+                    .channels()
+                    .any(|channel| scene_nodes.contains(&channel.target().node().index()))
+            })
+            .filter_map(|animation| animation.name().map(str::to_owned))
+            .collect();
+
+        loaded
+    }
+
+    /// Loads a node and its children, returning its index in
+    /// [`LoadedScene::nodes`].
+    fn load_gltf_node(
+        &mut self,
+        data: &GameData,
+        import: &mut GltfImport,
+        node: &gltf::Node,
+        parent_transform: Mat4,
+        loaded: &mut LoadedScene,
+        scene_nodes: &mut Vec<usize>,
+    ) -> usize {
+        let transform = parent_transform * Mat4::from_cols_array_2d(&node.transform().matrix());
+        scene_nodes.push(node.index());
+
+        let mut models = vec![];
+        if let Some(mesh) = node.mesh() {
+            let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+            for primitive in mesh.primitives() {
+                let reader =
+                    primitive.reader(|buffer| import.buffers.get(buffer.index()).map(|x| &x.0[..]));
+                let positions = match reader.read_positions() {
+                    Some(positions) => positions,
+                    None => continue,
+                };
+                let mut normals = reader.read_normals();
+                let mut tex_coords = reader.read_tex_coords(0).map(|x| x.into_f32());
+                let mut tex_coords_1 = reader.read_tex_coords(1).map(|x| x.into_f32());
+                let mut colors = reader.read_colors(0).map(|x| x.into_rgba_f32());
+                let vertices: Vec<TextureVertex> = positions
+                    .map(|pos| TextureVertex {
+                        pos,
+                        tex_coords: tex_coords
+                            .as_mut()
+                            .and_then(Iterator::next)
+                            .unwrap_or_default(),
+                        normals: normals
+                            .as_mut()
+                            .and_then(Iterator::next)
+                            .unwrap_or([0.0, 0.0, 1.0]),
+                        color: colors.as_mut().and_then(Iterator::next).unwrap_or([1.0; 4]),
+                        tex_coords_1: tex_coords_1
+                            .as_mut()
+                            .and_then(Iterator::next)
+                            .unwrap_or_default(),
+                    })
+                    .collect();
+                let indices = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect(),
+                    None => (0..vertices.len() as u32).collect(),
+                };
+
+                let material = import.material(data, &primitive.material());
+                if !loaded
+                    .materials
+                    .iter()
+                    .any(|existing| Arc::ptr_eq(existing, &material))
+                {
+                    loaded.materials.push(material.clone());
+                }
+
+                models.push(self.models.push(Model::new(
+                    data,
+                    vertices,
+                    indices,
+                    material,
+                    vec![Transform {
+                        translation,
+                        rotation,
+                        scale,
+                    }],
+                )));
+            }
+        }
+        loaded.models.extend_from_slice(&models);
+
+        let camera = node.camera().and_then(|camera| {
+            let converted = Camera::from_gltf(&camera, transform)?;
+            if let Some(name) = camera.name() {
+                self.camera_names
+                    .insert(name.to_owned(), self.cameras.len());
+            }
+            self.cameras.push(converted);
+            loaded.cameras.push(self.cameras.len() - 1);
+            Some(self.cameras.len() - 1)
+        });
+
+        let light = node.light().map(|light| {
+            self.lights.values.push(Light::from_gltf(&light, transform));
+            loaded.lights.push(self.lights.values.len() - 1);
+            self.lights.values.len() - 1
+        });
+
+        let index = loaded.nodes.len();
+        loaded.nodes.push(LoadedNode {
+            name: node.name().map(str::to_owned),
+            transform,
+            models,
+            camera,
+            light,
+            children: vec![],
+        });
+
+        let children = node
+            .children()
+            .map(|child| self.load_gltf_node(data, import, &child, transform, loaded, scene_nodes))
+            .collect();
+        loaded.nodes[index].children = children;
+
+        index
+    }
+}
+
+impl GltfImport {
+    fn new<P: AsRef<Path>>(path: P) -> Result<(gltf::Document, Self), gltf::Error> {
+        let (document, buffers, images) = gltf::import(path)?;
+        Ok((
+            document,
+            Self {
+                buffers,
+                images,
+                materials: HashMap::new(),
+            },
+        ))
+    }
+
+    fn from_slice(bytes: &[u8]) -> Result<(gltf::Document, Self), gltf::Error> {
+        let (document, buffers, images) = gltf::import_slice(bytes)?;
+        Ok((
+            document,
+            Self {
+                buffers,
+                images,
+                materials: HashMap::new(),
+            },
+        ))
+    }
+
+    /// Gets the material shared by every primitive using the glTF material.
+    fn material(&mut self, data: &GameData, material: &gltf::Material) -> Arc<Material> {
+        if let Some(material) = self.materials.get(&material.index()) {
+            return material.clone();
+        }
+
+        let pbr = material.pbr_metallic_roughness();
+        let factor = pbr.base_color_factor();
+        let converted = match pbr.base_color_texture().and_then(|info| {
+            let texture = info.texture();
+            let mut image = rgba_image(self.images.get(texture.source().index())?)?;
+            // The factor is linear but the color channels are sRGB
+            for pixel in image.pixels_mut() {
+                for (i, (channel, factor)) in pixel.0.iter_mut().zip(factor).enumerate() {
+                    let space = match i {
+                        3 => ColorSpace::Linear,
+                        _ => ColorSpace::Srgb,
+                    };
+                    *channel = space.encode(space.decode(*channel) * factor);
+                }
+            }
+            let sampler = match texture.sampler().mag_filter() {
+                Some(MagFilter::Nearest) => SamplerType::Nearest,
+                _ => SamplerType::Linear,
+            };
+            let transform = info.texture_transform();
+            let color_transform = TextureTransform {
+                offset: transform.as_ref().map_or(Vec2::ZERO, |x| x.offset().into()),
+                rotation: transform.as_ref().map_or(0.0, |x| x.rotation()),
+                scale: transform.as_ref().map_or(Vec2::ONE, |x| x.scale().into()),
+                tex_coord: transform
+                    .and_then(|x| x.tex_coord())
+                    .unwrap_or(info.tex_coord()),
+            };
+            Some(
+                Material::from_image(data, image, &sampler)
+                    .with_color_transform(data, color_transform),
+            )
+        }) {
+            Some(material) => material,
+            None => Material::from_color(data, factor),
+        };
+        let alpha_mode = match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => {
+                AlphaMode::Mask(material.alpha_cutoff().unwrap_or(0.5))
+            }
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        };
+
+        let converted = match material.occlusion_texture().and_then(|info| {
+            let image = rgba_image(self.images.get(info.texture().source().index())?)?;
+            // The gltf crate only reads the transform of color textures
+            let transform = json_transform(
+                info.extension_value("KHR_texture_transform"),
+                info.tex_coord(),
+            );
+            Some((image, info.strength(), transform))
+        }) {
+            Some((image, strength, transform)) => converted
+                .with_occlusion(data, image, strength)
+                .with_occlusion_transform(data, transform),
+            None => converted,
+        };
+
+        let converted = Arc::new(
+            converted
+                .with_metallic_roughness(data, pbr.metallic_factor(), pbr.roughness_factor())
+                .with_alpha_mode(data, alpha_mode)
+                .with_double_sided(material.double_sided()),
+        );
+        self.materials.insert(material.index(), converted.clone());
+        converted
+    }
+}
+
+/// Reads a `KHR_texture_transform` extension, which overrides `tex_coord` if
+/// it has its own.
+fn json_transform(extension: Option<&serde_json::Value>, tex_coord: u32) -> TextureTransform {
+    let default = TextureTransform::default();
+    let vec2 = |key, default: Vec2| {
+        extension
+            .and_then(|extension| extension.get(key)?.as_array())
+            .and_then(|value| {
+                Some(Vec2::new(
+                    value.first()?.as_f64()? as f32,
+                    value.get(1)?.as_f64()? as f32,
+                ))
+            })
+            .unwrap_or(default)
+    };
+    let number = |key| extension.and_then(|extension| extension.get(key)?.as_f64());
+    TextureTransform {
+        offset: vec2("offset", default.offset),
+        rotation: number("rotation").map_or(default.rotation, |x| x as f32),
+        scale: vec2("scale", default.scale),
+        tex_coord: number("texCoord").map_or(tex_coord, |x| x as u32),
+    }
+}
+
+/// Converts the decoded pixels of a glTF image to 8 bit RGBA, returning `None`
+/// for floating point formats.
+fn rgba_image(image: &gltf::image::Data) -> Option<RgbaImage> {
+    let (channels, bytes_per_channel) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        _ => return None,
+    };
+
+    let pixels = image
+        .pixels
+        .chunks_exact(channels * bytes_per_channel)
+        .flat_map(|pixel| {
+            // 16 bit channels are stored in native endianness, only the most
+            // significant byte is kept.
+            let channel = |i: usize| match bytes_per_channel {
+                1 => pixel[i],
+                _ => (u16::from_ne_bytes([pixel[i * 2], pixel[i * 2 + 1]]) >> 8) as u8,
+            };
+            match channels {
+                1 => [channel(0), channel(0), channel(0), 255],
+                2 => [channel(0), channel(0), channel(0), channel(1)],
+                3 => [channel(0), channel(1), channel(2), 255],
+                _ => [channel(0), channel(1), channel(2), channel(3)],
+            }
+        })
+        .collect();
+
+    RgbaImage::from_raw(image.width, image.height, pixels)
+}
@@ -1,14 +1,13 @@
-struct VertexInput {
-    @location(0) pos: vec3<f32>,
-    @location(1) tex_coords: vec2<f32>,
-    @location(6) normal: vec3<f32>,
-};
-
 struct VertexOutput {
     @builtin(position) pos: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
     @location(1) world_pos: vec3<f32>,
     @location(2) normal: vec3<f32>,
+    @location(3) color: vec4<f32>,
+    // The distance in front of the camera, which a perspective projection puts
+    // in w
+    @location(4) view_depth: f32,
+    @location(5) occlusion_uv: vec2<f32>,
 };
 
 struct Transform {
@@ -22,7 +21,8 @@ struct Transform {
 var<uniform> camera: Transform;
 
 @vertex
-fn vertex_main(in: VertexInput, transform: Transform) -> VertexOutput {
+fn vertex_main(input: VertexInput, transform: Transform) -> VertexOutput {
+    let in = vertex_input(input);
     let transform_matrix = mat4x4<f32>(
         transform.data0,
         transform.data1,
@@ -39,9 +39,12 @@ fn vertex_main(in: VertexInput, transform: Transform) -> VertexOutput {
     var output: VertexOutput;
     let world_pos = transform_matrix * vec4<f32>(in.pos, 1.0);
     output.pos = camera_matrix * world_pos;
-    output.tex_coords = in.tex_coords;
+    output.view_depth = output.pos.w;
+    output.tex_coords = material_uv(in);
+    output.occlusion_uv = occlusion_uv(in);
     output.world_pos = world_pos.xyz;
-    output.normal = (transform_matrix * vec4<f32>(in.normal, 1.0)).xyz;
+    output.normal = normalize((transform_matrix * vec4<f32>(in.normal, 0.0)).xyz);
+    output.color = in.color;
     return output;
 }
 
@@ -49,10 +52,28 @@ fn vertex_main(in: VertexInput, transform: Transform) -> VertexOutput {
 var color_texture: texture_2d<f32>;
 @group(0)@binding(1)
 var color_texture_sampler: sampler;
+@group(0)@binding(3)
+var occlusion_texture: texture_2d<f32>;
+
+// Discards cut out fragments and removes the alpha of opaque ones.
+fn alpha_mode(color: vec4<f32>) -> vec4<f32> {
+    if material.alpha_mode == 1u && color.a < material.alpha_cutoff {
+        discard;
+    }
+    if material.alpha_mode == 0u {
+        return vec4<f32>(color.rgb, 1.0);
+    }
+    return color;
+}
 
 struct Light {
     pos: vec3<f32>,
+    ty: f32,
     color: vec3<f32>,
+    intensity: f32,
+    direction: vec3<f32>,
+    range: f32,
+    cone: vec4<f32>,
 }
 
 struct LightArray {
@@ -62,11 +83,301 @@ struct LightArray {
 @group(2)@binding(0)
 var<storage> light: LightArray;
 
+struct Shadow {
+    view_proj: array<mat4x4<f32>, 4>,
+    rect: array<vec4<f32>, 4>,
+    // Depth bias, normal bias, number of cascades and cascade blend
+    settings: vec4<f32>,
+    // The cube map of point lights and their range, then the range of spot
+    // lights
+    cube: vec4<f32>,
+}
+
+struct ShadowArray {
+    shadows: array<Shadow>
+}
+
+@group(2)@binding(1)
+var<storage> shadow: ShadowArray;
+@group(2)@binding(2)
+var shadow_atlas: texture_depth_2d;
+@group(2)@binding(3)
+var shadow_sampler: sampler_comparison;
+@group(2)@binding(4)
+var shadow_cubes: texture_depth_cube_array;
+
+struct Ambient {
+    sky: vec4<f32>,
+    ground: vec4<f32>,
+    // The intensity of the environment, which is used instead of the sky and
+    // ground when above 0
+    environment: vec4<f32>,
+}
+
+@group(2)@binding(5)
+var<uniform> ambient: Ambient;
+@group(2)@binding(6)
+var irradiance_map: texture_cube<f32>;
+@group(2)@binding(7)
+var specular_map: texture_cube<f32>;
+@group(2)@binding(8)
+var brdf_lut: texture_2d<f32>;
+@group(2)@binding(9)
+var environment_sampler: sampler;
+
+// Projects a point into a shadow map, giving its uv, depth and w
+fn shadow_coords(view_proj: mat4x4<f32>, pos: vec3<f32>) -> vec4<f32> {
+    let clip = view_proj * vec4<f32>(pos, 1.0);
+    let ndc = clip.xyz / clip.w;
+    return vec4<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5, ndc.z, clip.w);
+}
+
+fn in_shadow_map(coords: vec4<f32>) -> bool {
+    return coords.w > 0.0 && coords.z <= 1.0 && all(coords.xy >= vec2<f32>(0.0)) && all(coords.xy <= vec2<f32>(1.0));
+}
+
+// How much of a light reaches a point in one shadow map, filtered over 3x3
+// texels
+fn sample_shadow(rect: vec4<f32>, coords: vec4<f32>, bias: f32) -> f32 {
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_atlas));
+    // Keep samples inside the part of the atlas used by this shadow map
+    let min_uv = rect.xy + texel * 0.5;
+    let max_uv = rect.xy + rect.zw - texel * 0.5;
+    let center = rect.xy + coords.xy * rect.zw;
+    var lit = 0.0;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            let sample_uv = clamp(center + vec2<f32>(f32(x), f32(y)) * texel, min_uv, max_uv);
+            lit += textureSampleCompareLevel(shadow_atlas, shadow_sampler, sample_uv, coords.z - bias);
+        }
+    }
+    return lit / 9.0;
+}
+
+// How much of a light reaches a point
+fn shadow_factor(index: u32, world_pos: vec3<f32>, normal: vec3<f32>) -> f32 {
+    let settings = shadow.shadows[index].settings;
+    if !RECEIVE_SHADOWS || (shadow.shadows[index].rect[0].z == 0.0 && shadow.shadows[index].cube.y == 0.0) {
+        return 1.0;
+    }
+
+    let pos = world_pos + normal * settings.y;
+    let cube = shadow.shadows[index].cube;
+    if cube.y > 0.0 {
+        let offset = pos - light.lights[index].pos;
+        return textureSampleCompareLevel(shadow_cubes, shadow_sampler, offset, i32(cube.x), length(offset) / cube.y - settings.x);
+    }
+
+    let cascades = u32(settings.z);
+    // Cascades go from closest to furthest, so the first one with the point
+    // in it has the most detail
+    for (var i = 0u; i < cascades; i++) {
+        var coords = shadow_coords(shadow.shadows[index].view_proj[i], pos);
+        if cube.z > 0.0 {
+            // Spot lights store the distance to the light
+            coords.z = distance(pos, light.lights[index].pos) / cube.z;
+        }
+        if !in_shadow_map(coords) {
+            continue;
+        }
+        let lit = sample_shadow(shadow.shadows[index].rect[i], coords, settings.x);
+
+        // Fade into the next cascade near the edges, 0 at the edge and 1 in
+        // the middle
+        let edge = min(min(coords.x, coords.y), min(1.0 - coords.x, 1.0 - coords.y)) * 2.0;
+        if i + 1u < cascades && edge < settings.w {
+            let next = shadow_coords(shadow.shadows[index].view_proj[i + 1u], pos);
+            if in_shadow_map(next) {
+                let next_lit = sample_shadow(shadow.shadows[index].rect[i + 1u], next, settings.x);
+                return mix(next_lit, lit, edge / settings.w);
+            }
+        }
+        return lit;
+    }
+    return 1.0;
+}
+
+struct ClusterGrid {
+    view: mat4x4<f32>,
+    // The width and height of the screen, and the near and far depths of the
+    // slices
+    screen: vec4<f32>,
+    size: vec4<u32>,
+}
+
+struct ClusterCells {
+    // The offset into the light indices and number of lights of each cluster
+    cells: array<vec2<u32>>,
+}
+
+struct LightIndices {
+    indices: array<u32>,
+}
+
+@group(3)@binding(0)
+var<uniform> cluster_grid: ClusterGrid;
+@group(3)@binding(1)
+var<storage> clusters: ClusterCells;
+@group(3)@binding(2)
+var<storage> light_indices: LightIndices;
+
+// The cluster a fragment is in, slices get exponentially deeper away from the
+// camera
+fn cluster(frag_pos: vec4<f32>, world_pos: vec3<f32>) -> vec2<u32> {
+    let size = cluster_grid.size;
+    let tile = vec2<u32>(frag_pos.xy / cluster_grid.screen.xy * vec2<f32>(size.xy));
+    let depth = max(-(cluster_grid.view * vec4<f32>(world_pos, 1.0)).z, cluster_grid.screen.z);
+    let slice = log(depth / cluster_grid.screen.z) / log(cluster_grid.screen.w / cluster_grid.screen.z) * f32(size.z);
+    let index = vec3<u32>(min(tile, size.xy - 1u), min(u32(slice), size.z - 1u));
+    return clusters.cells[(index.z * size.y + index.y) * size.x + index.x];
+}
+
+// The position of the camera, taken from the view used for clustering
+fn camera_pos() -> vec3<f32> {
+    let view = cluster_grid.view;
+    let rotation = mat3x3<f32>(view[0].xyz, view[1].xyz, view[2].xyz);
+    return -(transpose(rotation) * view[3].xyz);
+}
+
+// The light reaching a point and the direction towards where it comes from
+struct Radiance {
+    color: vec3<f32>,
+    direction: vec3<f32>,
+}
+
+fn light_radiance(light: Light, world_pos: vec3<f32>) -> Radiance {
+    var radiance: Radiance;
+    // Directional lights
+    if light.ty == 1.0 {
+        radiance.color = light.color * light.intensity;
+        radiance.direction = -light.direction;
+        return radiance;
+    }
+
+    let offset = light.pos - world_pos;
+    let dist = length(offset);
+    radiance.direction = offset / dist;
+
+    var attenuation = 1.0 / max(dist * dist, 0.0001);
+    if light.range > 0.0 {
+        let ratio = dist / light.range;
+        attenuation *= pow(clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0), 2.0);
+    }
+
+    // Spot lights
+    if light.ty == 2.0 {
+        let angle = dot(light.direction, -radiance.direction);
+        attenuation *= smoothstep(light.cone.y, light.cone.x, angle);
+    }
+
+    radiance.color = light.color * light.intensity * attenuation;
+    return radiance;
+}
+
+let PI: f32 = 3.14159265;
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let denom = n_dot_h * n_dot_h * (a * a - 1.0) + 1.0;
+    return a * a / (PI * denom * denom + 0.0001);
+}
+
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Rough surfaces reflect less at grazing angles, from "Adopting a physically
+// based shading model" by Lagarde
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    return f0 + (max(vec3<f32>(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Cook-Torrance with a GGX distribution, following the glTF metallic roughness
+// model
+fn brdf(albedo: vec3<f32>, f0: vec3<f32>, normal: vec3<f32>, view: vec3<f32>, light_dir: vec3<f32>) -> vec3<f32> {
+    let halfway = normalize(view + light_dir);
+    let n_dot_v = max(dot(normal, view), 0.0001);
+    let n_dot_l = max(dot(normal, light_dir), 0.0);
+    let fresnel = fresnel_schlick(max(dot(halfway, view), 0.0), f0);
+    let specular = distribution_ggx(max(dot(normal, halfway), 0.0), material.roughness)
+        * geometry_smith(n_dot_v, n_dot_l, material.roughness) * fresnel
+        / (4.0 * n_dot_v * n_dot_l + 0.0001);
+    let diffuse = (1.0 - fresnel) * (1.0 - material.metallic) * albedo / PI;
+    return (diffuse + specular) * n_dot_l;
+}
+
+fn ambient_light(albedo: vec3<f32>, f0: vec3<f32>, normal: vec3<f32>, view: vec3<f32>) -> vec3<f32> {
+    if ambient.environment.x <= 0.0 {
+        let hemisphere = mix(ambient.ground.rgb, ambient.sky.rgb, normal.y * 0.5 + 0.5);
+        return hemisphere * albedo * (1.0 - material.metallic);
+    }
+
+    let n_dot_v = max(dot(normal, view), 0.0);
+    let fresnel = fresnel_schlick_roughness(n_dot_v, f0, material.roughness);
+    let irradiance = textureSample(irradiance_map, environment_sampler, normal).rgb;
+    let diffuse = (1.0 - fresnel) * (1.0 - material.metallic) * irradiance * albedo;
+
+    let reflection = reflect(-view, normal);
+    let level = material.roughness * f32(textureNumLevels(specular_map) - 1);
+    let prefiltered = textureSampleLevel(specular_map, environment_sampler, reflection, level).rgb;
+    let scale_bias = textureSample(brdf_lut, environment_sampler, vec2<f32>(n_dot_v, material.roughness)).rg;
+    let specular = prefiltered * (fresnel * scale_bias.x + scale_bias.y);
+
+    return (diffuse + specular) * ambient.environment.x;
+}
+
+fn shade(in: VertexOutput, front_facing: bool) -> vec4<f32> {
+    let occlusion = textureSample(occlusion_texture, color_texture_sampler, in.occlusion_uv).r;
+    let color = alpha_mode(textureSample(color_texture, color_texture_sampler, in.tex_coords) * in.color);
+    var normal = normalize(in.normal);
+    // Only double sided materials have back faces drawn
+    if !front_facing {
+        normal = -normal;
+    }
+    let view = normalize(camera_pos() - in.world_pos);
+    // Dielectrics reflect about 4% of light head on, metals reflect their color
+    let f0 = mix(vec3<f32>(0.04), color.rgb, material.metallic);
+
+    var lighting = ambient_light(color.rgb, f0, normal, view) * mix(1.0, occlusion, material.occlusion_strength);
+    let cell = cluster(in.pos, in.world_pos);
+    for (var i = 0u; i < cell.y; i++) {
+        let index = light_indices.indices[cell.x + i];
+        let radiance = light_radiance(light.lights[index], in.world_pos);
+        lighting += brdf(color.rgb, f0, normal, view, radiance.direction) * radiance.color * shadow_factor(index, in.world_pos, normal);
+    }
+    return vec4<f32>(lighting, color.a);
+}
+
+@fragment
+fn fragment_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    return shade(in, front_facing);
+}
+
+@fragment
+fn fragment_main_oit(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> OitOutput {
+    return weighted_oit(shade(in, front_facing), in.view_depth);
+}
+
+// Colors fragments by how many lights their cluster has, from blue with none
+// to green with 8 and red with 16 or more
+fn light_count_color(in: VertexOutput) -> vec4<f32> {
+    let heat = clamp(f32(cluster(in.pos, in.world_pos).y) / 16.0, 0.0, 1.0);
+    let cold = mix(vec3<f32>(0.0, 0.0, 1.0), vec3<f32>(0.0, 1.0, 0.0), clamp(heat * 2.0, 0.0, 1.0));
+    return vec4<f32>(mix(cold, vec3<f32>(1.0, 0.0, 0.0), clamp(heat * 2.0 - 1.0, 0.0, 1.0)), 1.0);
+}
+
+@fragment
+fn light_count_fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    return light_count_color(in);
+}
+
 @fragment
-fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let color = textureSample(color_texture, color_texture_sampler, in.tex_coords);
-    let dist = distance(light.lights[0].pos, in.world_pos);
-    let light_direction = normalize(light.lights[0].pos - in.world_pos);
-    let light_facing = dot(in.normal, light_direction);
-    return vec4<f32>(light_facing, light_facing, light_facing, 1.0) * dist * color;
+fn light_count_fragment_oit(in: VertexOutput) -> OitOutput {
+    return weighted_oit(light_count_color(in), in.view_depth);
 }
@@ -0,0 +1,691 @@
+use std::{collections::HashMap, f32::consts::FRAC_PI_2, num::NonZeroU32};
+
+use glam::{Mat4, Vec3};
+use rhachis::{renderers::Transform, GameData, IdMap};
+use wgpu::{BindGroup, Buffer, RenderPipeline, Sampler, TextureView};
+
+use crate::{
+    camera::{Camera, NEAR},
+    light::{Light, LightType},
+    material::{AlphaMode, Material},
+    model::Model,
+    vertex::VertexLayout,
+};
+
+/// The width and height of the texture every shadow map is packed into.
+pub const ATLAS_SIZE: u32 = 4096;
+const ATLAS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+/// The most shadow maps a directional light can split the view into.
+pub const MAX_CASCADES: usize = 4;
+/// The width and height of each face of the cube maps used by point lights.
+pub const CUBE_SIZE: u32 = 512;
+/// Dynamic offsets into uniform buffers have to be aligned to this.
+const MATRIX_STRIDE: u64 = 256;
+
+/// The shadow atlas, the cube maps of point lights and the data the lit
+/// shader needs to sample them.
+pub(crate) struct Shadows {
+    atlas: TextureView,
+    pub sampler: Sampler,
+    /// A [`ShadowUniform`] for every light, even ones without shadows.
+    pub uniforms: Buffer,
+    /// Room for [`MAX_CASCADES`] [`ShadowView`]s for each light, each in its
+    /// own block of [`MATRIX_STRIDE`] bytes, used when rendering the shadow
+    /// maps.
+    matrices: Buffer,
+    matrices_bind_group: BindGroup,
+    /// The block in `matrices`, the viewport in the atlas of each shadow map
+    /// and whether it stores the distance to the light.
+    tiles: Vec<(u64, [f32; 4], bool)>,
+    cubes: Cubes,
+    /// Variants of the shadow pipeline for every vertex layout that casts,
+    /// and whether they store the distance to the light.
+    pipelines: HashMap<(VertexLayout, bool), RenderPipeline>,
+}
+
+impl Shadows {
+    pub fn new(data: &GameData, lights: usize, point_shadows: usize) -> Self {
+        let atlas = data
+            .graphics
+            .device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("shadow_atlas"),
+                size: wgpu::Extent3d {
+                    width: ATLAS_SIZE,
+                    height: ATLAS_SIZE,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: ATLAS_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let sampler = data
+            .graphics
+            .device
+            .create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("shadow_sampler"),
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                compare: Some(wgpu::CompareFunction::LessEqual),
+                ..Default::default()
+            });
+
+        let (uniforms, matrices, matrices_bind_group) = Self::buffers(data, lights);
+
+        Self {
+            atlas,
+            sampler,
+            uniforms,
+            matrices,
+            matrices_bind_group,
+            tiles: vec![],
+            cubes: Cubes::new(data, point_shadows),
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// How many point lights can cast shadows at once.
+    pub fn point_shadows(&self) -> usize {
+        self.cubes.capacity
+    }
+
+    /// Remakes the cube maps to fit `point_shadows` point lights.
+    pub fn set_point_shadows(&mut self, data: &GameData, point_shadows: usize) {
+        self.cubes = Cubes::new(data, point_shadows);
+    }
+
+    /// Remakes the buffers when the number of lights changes.
+    pub fn resize(&mut self, data: &GameData, lights: usize) {
+        (self.uniforms, self.matrices, self.matrices_bind_group) = Self::buffers(data, lights);
+    }
+
+    pub fn atlas(&self) -> &TextureView {
+        &self.atlas
+    }
+
+    pub fn cubes(&self) -> &TextureView {
+        &self.cubes.array
+    }
+
+    /// Fits every shadow map into the atlas and uploads their matrices,
+    /// directional lights cover the view of `camera`. Point lights get cube
+    /// maps in order of how noticeable they are until they run out.
+    pub fn update(&mut self, data: &GameData, lights: &[Light], camera: &Camera) {
+        let maps: usize = lights.iter().map(shadow_maps).sum();
+        let columns = (maps as f32).sqrt().ceil().max(1.0) as u32;
+        let tile_size = (ATLAS_SIZE / columns) as f32;
+        self.tiles.clear();
+        let mut uniforms = vec![ShadowUniform::default(); lights.len().max(1)];
+        for (i, light) in lights.iter().enumerate() {
+            let settings = match light.shadows {
+                Some(settings) => settings,
+                None => continue,
+            };
+            let matrices = light_matrices(light, &settings, camera, tile_size);
+            // Spot lights store the distance to the light like point lights,
+            // as the depth of a perspective projection is too uneven to bias
+            let spot_range = match light.ty {
+                LightType::Spot { .. } => shadow_range(light),
+                _ => 0.0,
+            };
+            let uniform = &mut uniforms[i];
+            uniform.cube[2] = spot_range;
+            uniform.settings = [
+                settings.depth_bias,
+                settings.normal_bias,
+                matrices.len() as f32,
+                settings.cascade_blend,
+            ];
+
+            for (cascade, matrix) in matrices.into_iter().enumerate() {
+                let tile = self.tiles.len() as u32;
+                let [x, y] = [tile % columns, tile / columns].map(|x| x as f32);
+                uniform.view_proj[cascade] = matrix.to_cols_array_2d();
+                let viewport = [x * tile_size, y * tile_size, tile_size, tile_size];
+                // Tiles don't always fill the atlas, so the rect is made from
+                // the same viewport the map is rendered into
+                uniform.rect[cascade] = viewport.map(|x| x / ATLAS_SIZE as f32);
+
+                let block = (i * MAX_CASCADES + cascade) as u64;
+                self.tiles.push((block, viewport, spot_range > 0.0));
+                data.graphics.queue.write_buffer(
+                    &self.matrices,
+                    block * MATRIX_STRIDE,
+                    bytemuck::bytes_of(&ShadowView {
+                        view_proj: matrix.to_cols_array_2d(),
+                        light: light.pos.extend(spot_range).to_array(),
+                    }),
+                );
+            }
+        }
+
+        let mut points: Vec<usize> = (0..lights.len())
+            .filter(|i| lights[*i].shadows.is_some() && matches!(lights[*i].ty, LightType::Point))
+            .collect();
+        points.sort_by(|a, b| {
+            importance(&lights[*b], camera).total_cmp(&importance(&lights[*a], camera))
+        });
+        points.truncate(self.cubes.capacity);
+        for (cube, &i) in points.iter().enumerate() {
+            let light = &lights[i];
+            let range = shadow_range(light);
+            uniforms[i].cube = [cube as f32, range, 0.0, 0.0];
+            for (face, matrix) in cube_matrices(light.pos, range).into_iter().enumerate() {
+                data.graphics.queue.write_buffer(
+                    &self.cubes.matrices,
+                    (cube * 6 + face) as u64 * MATRIX_STRIDE,
+                    bytemuck::bytes_of(&ShadowView {
+                        view_proj: matrix.to_cols_array_2d(),
+                        light: light.pos.extend(range).to_array(),
+                    }),
+                );
+            }
+        }
+        self.cubes.lights = points;
+
+        data.graphics
+            .queue
+            .write_buffer(&self.uniforms, 0, bytemuck::cast_slice(&uniforms));
+    }
+
+    /// Makes sure the shadow pipelines exist for the model.
+    pub fn prepare(&mut self, data: &GameData, model: &Model) {
+        if model.cast_shadows {
+            for distance in [false, true] {
+                self.pipelines
+                    .entry((model.layout, distance))
+                    .or_insert_with(|| shadow_pipeline(data, model.layout, distance));
+            }
+        }
+    }
+
+    /// Renders every shadow map into its part of the atlas, and every face of
+    /// the cube maps in use.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, models: &IdMap<Model>) {
+        if !self.tiles.is_empty() {
+            self.render_atlas(encoder, models);
+        }
+
+        for layer in 0..self.cubes.lights.len() * 6 {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("point_shadow_pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.cubes.faces[layer],
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_bind_group(
+                1,
+                &self.cubes.matrices_bind_group,
+                &[(layer as u64 * MATRIX_STRIDE) as u32],
+            );
+            self.draw_casters(&mut render_pass, models, true);
+        }
+    }
+
+    fn render_atlas(&self, encoder: &mut wgpu::CommandEncoder, models: &IdMap<Model>) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("shadow_pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.atlas,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        for (block, [x, y, width, height], distance) in &self.tiles {
+            render_pass.set_viewport(*x, *y, *width, *height, 0.0, 1.0);
+            render_pass.set_bind_group(
+                1,
+                &self.matrices_bind_group,
+                &[(block * MATRIX_STRIDE) as u32],
+            );
+            self.draw_casters(&mut render_pass, models, *distance);
+        }
+    }
+
+    fn draw_casters<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        models: &'a IdMap<Model>,
+        distance: bool,
+    ) {
+        for model in models {
+            // Blended models let light through
+            if !model.cast_shadows || model.material.alpha_mode == AlphaMode::Blend {
+                continue;
+            }
+            if let Some(pipeline) = self.pipelines.get(&(model.layout, distance)) {
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(0, &model.material.bind_group, &[]);
+                model.draw(render_pass);
+            }
+        }
+    }
+
+    fn buffers(data: &GameData, lights: usize) -> (Buffer, Buffer, BindGroup) {
+        let lights = lights.max(1) as u64;
+        let uniforms = data.graphics.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("shadow_uniforms"),
+            size: lights * std::mem::size_of::<ShadowUniform>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let (matrices, bind_group) = view_buffer(data, lights * MAX_CASCADES as u64);
+
+        (uniforms, matrices, bind_group)
+    }
+}
+
+/// Cube maps for the point lights casting shadows this frame, they store the
+/// distance to the light divided by its range.
+struct Cubes {
+    /// Every cube map as one texture, sampled by the lit shader.
+    array: TextureView,
+    /// Every face of every cube map, each is rendered to separately.
+    faces: Vec<TextureView>,
+    /// A [`ShadowView`] for each face in its own block of [`MATRIX_STRIDE`]
+    /// bytes.
+    matrices: Buffer,
+    matrices_bind_group: BindGroup,
+    /// How many cube maps there are.
+    capacity: usize,
+    /// The light using each cube map this frame.
+    lights: Vec<usize>,
+}
+
+impl Cubes {
+    fn new(data: &GameData, capacity: usize) -> Self {
+        let layers = capacity.max(1) as u32 * 6;
+        let texture = data
+            .graphics
+            .device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("shadow_cubes"),
+                size: wgpu::Extent3d {
+                    width: CUBE_SIZE,
+                    height: CUBE_SIZE,
+                    depth_or_array_layers: layers,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: ATLAS_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+            });
+
+        let array = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::CubeArray),
+            ..Default::default()
+        });
+        let faces = (0..layers)
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let (matrices, matrices_bind_group) = view_buffer(data, layers as u64);
+
+        Self {
+            array,
+            faces,
+            matrices,
+            matrices_bind_group,
+            capacity,
+            lights: vec![],
+        }
+    }
+}
+
+/// Settings for the shadows cast by a light.
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowSettings {
+    /// Subtracted from the depth of fragments so surfaces don't shadow
+    /// themselves.
+    pub depth_bias: f32,
+    /// How far fragments are moved along their normal before being tested,
+    /// which helps on surfaces facing away from the light.
+    pub normal_bias: f32,
+    /// How far from the camera directional lights cast shadows.
+    pub distance: f32,
+    /// How many shadow maps the view of the camera is split into for
+    /// directional lights, up to [`MAX_CASCADES`]. Closer cascades cover less
+    /// area, so their shadows are sharper.
+    pub cascades: u32,
+    /// Where the cascades are split, from evenly spaced at 0 to growing
+    /// logarithmically at 1, which gives the closest ones more detail.
+    pub cascade_split: f32,
+    /// How much of each cascade fades into the next one at its edges.
+    pub cascade_blend: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            depth_bias: 0.002,
+            normal_bias: 0.02,
+            distance: 50.0,
+            cascades: 4,
+            cascade_split: 0.75,
+            cascade_blend: 0.1,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+    view_proj: [[[f32; 4]; 4]; MAX_CASCADES],
+    /// The area of the atlas used by each cascade, with a width of 0 if the
+    /// light has no shadows.
+    rect: [[f32; 4]; MAX_CASCADES],
+    /// The depth bias, normal bias, number of cascades and cascade blend.
+    settings: [f32; 4],
+    /// The cube map of a point light and its range, with a range of 0 if it
+    /// has no cube map this frame, then the range of a spot light.
+    cube: [f32; 4],
+}
+
+/// What the shadow shader needs to render one shadow map.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowView {
+    view_proj: [[f32; 4]; 4],
+    /// The position and range of a point or spot light.
+    light: [f32; 4],
+}
+
+/// How many shadow maps a light needs.
+fn shadow_maps(light: &Light) -> usize {
+    match (light.shadows, light.ty) {
+        (None, _) | (_, LightType::Point) => 0,
+        (Some(settings), LightType::Directional(_)) => {
+            (settings.cascades as usize).clamp(1, MAX_CASCADES)
+        }
+        (Some(_), LightType::Spot { .. }) => 1,
+    }
+}
+
+/// The view projection matrix of each shadow map in the atlas for a light,
+/// with directional lights ordered from the closest cascade to the furthest.
+/// Point lights use cube maps instead.
+fn light_matrices(
+    light: &Light,
+    settings: &ShadowSettings,
+    camera: &Camera,
+    tile_size: f32,
+) -> Vec<Mat4> {
+    match light.ty {
+        LightType::Point => vec![],
+        LightType::Directional(dir) => {
+            let cascades = shadow_maps(light);
+            // Mixes even and logarithmic splits, from "Parallel-Split Shadow
+            // Maps on Programmable GPUs" by Zhang et al.
+            let splits: Vec<f32> = (0..=cascades)
+                .map(|i| {
+                    let t = i as f32 / cascades as f32;
+                    let even = NEAR + (settings.distance - NEAR) * t;
+                    let log = NEAR * (settings.distance / NEAR).powf(t);
+                    even + (log - even) * settings.cascade_split
+                })
+                .collect();
+            splits
+                .windows(2)
+                .map(|split| {
+                    cascade_matrix(
+                        dir,
+                        camera,
+                        split[0],
+                        split[1],
+                        tile_size,
+                        settings.distance,
+                    )
+                })
+                .collect()
+        }
+        LightType::Spot {
+            dir, outer_angle, ..
+        } => {
+            let view = Mat4::look_to_rh(light.pos, dir, up(dir));
+            let proj =
+                Mat4::perspective_rh((outer_angle * 2.0).min(3.1), 1.0, 0.05, shadow_range(light));
+            vec![proj * view]
+        }
+    }
+}
+
+/// How far the shadows of a point or spot light reach.
+fn shadow_range(light: &Light) -> f32 {
+    light.range.unwrap_or(100.0)
+}
+
+/// How noticeable the shadows of a light are, brighter and closer lights
+/// matter more.
+fn importance(light: &Light, camera: &Camera) -> f32 {
+    light.intensity / camera.pos.distance_squared(light.pos).max(1.0)
+}
+
+/// The view projection matrix of each face of a cube map around `pos`, in the
+/// order of the layers of a cube texture.
+fn cube_matrices(pos: Vec3, range: f32) -> [Mat4; 6] {
+    let proj = Mat4::perspective_rh(FRAC_PI_2, 1.0, 0.05, range);
+    // These are the usual OpenGL directions, which put faces upside down
+    // compared to how wgpu samples them, so y is flipped back
+    let flip = Mat4::from_scale(Vec3::new(1.0, -1.0, 1.0));
+    [
+        (Vec3::X, Vec3::NEG_Y),
+        (Vec3::NEG_X, Vec3::NEG_Y),
+        (Vec3::Y, Vec3::Z),
+        (Vec3::NEG_Y, Vec3::NEG_Z),
+        (Vec3::Z, Vec3::NEG_Y),
+        (Vec3::NEG_Z, Vec3::NEG_Y),
+    ]
+    .map(|(dir, up)| flip * proj * Mat4::look_to_rh(pos, dir, up))
+}
+
+/// An orthographic projection covering the view of the camera from `near`
+/// to `far`, that also reaches `reach` back towards the light to include
+/// models casting shadows into it. It's fit around a sphere so its size
+/// doesn't change as the camera turns, and only moves in whole texels so the
+/// edges of shadows don't shimmer.
+fn cascade_matrix(
+    dir: Vec3,
+    camera: &Camera,
+    near: f32,
+    far: f32,
+    tile_size: f32,
+    reach: f32,
+) -> Mat4 {
+    let forward = camera.dir();
+    let right = forward.cross(up(forward)).normalize();
+    let camera_up = right.cross(forward);
+    let tan = (camera.fov / 2.0).tan();
+    let corners: Vec<Vec3> = [near, far]
+        .into_iter()
+        .flat_map(|depth| {
+            let center = camera.pos + forward * depth;
+            let up = camera_up * depth * tan;
+            let right = right * depth * tan * camera.aspect;
+            [
+                center - right - up,
+                center + right - up,
+                center - right + up,
+                center + right + up,
+            ]
+        })
+        .collect();
+
+    let center = corners.iter().sum::<Vec3>() / corners.len() as f32;
+    // Rounded so floating point error doesn't change the size between frames
+    let radius = corners
+        .iter()
+        .map(|corner| corner.distance(center))
+        .fold(0.0, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let view = Mat4::look_to_rh(Vec3::ZERO, dir, up(dir));
+    let center = view.transform_point3(center);
+    let texel = radius * 2.0 / tile_size;
+    let [x, y] = [center.x, center.y].map(|x| (x / texel).floor() * texel);
+    let proj = Mat4::orthographic_rh(
+        x - radius,
+        x + radius,
+        y - radius,
+        y + radius,
+        -center.z - radius - reach,
+        -center.z + radius,
+    );
+    proj * view
+}
+
+/// An up direction that isn't parallel to `dir`.
+fn up(dir: Vec3) -> Vec3 {
+    match dir.normalize_or_zero().y.abs() > 0.99 {
+        true => Vec3::X,
+        false => Vec3::Y,
+    }
+}
+
+/// A buffer with room for `blocks` [`ShadowView`]s, and a bind group to pick
+/// one with a dynamic offset.
+fn view_buffer(data: &GameData, blocks: u64) -> (Buffer, BindGroup) {
+    let buffer = data.graphics.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("shadow_views"),
+        size: blocks * MATRIX_STRIDE,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let bind_group = data
+        .graphics
+        .device
+        .create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &matrix_bind_group_layout(data),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<ShadowView>() as u64),
+                }),
+            }],
+        });
+
+    (buffer, bind_group)
+}
+
+fn matrix_bind_group_layout(data: &GameData) -> wgpu::BindGroupLayout {
+    data.graphics
+        .device
+        .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(
+                        std::mem::size_of::<ShadowView>() as u64
+                    ),
+                },
+                count: None,
+            }],
+        })
+}
+
+fn shadow_pipeline(data: &GameData, layout: VertexLayout, distance: bool) -> RenderPipeline {
+    let shader = data
+        .graphics
+        .device
+        .create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shadow.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                format!(
+                    "{}{}\n{}",
+                    layout.shader_header(),
+                    include_str!("material.wgsl"),
+                    include_str!("shadow.wgsl")
+                )
+                .into(),
+            ),
+        });
+
+    let pipeline_layout =
+        data.graphics
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[
+                    &Material::bind_group_layout(data),
+                    &matrix_bind_group_layout(data),
+                ],
+                push_constant_ranges: &[],
+            });
+
+    let attributes = layout.wgpu_attributes();
+    data.graphics
+        .device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("shadow_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "shadow_vertex",
+                buffers: &[
+                    wgpu::VertexBufferLayout {
+                        array_stride: layout.stride(),
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &attributes,
+                    },
+                    Transform::desc(),
+                ],
+            },
+            // Both sides are drawn so thin and open models still cast shadows
+            primitive: wgpu::PrimitiveState {
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: ATLAS_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: match distance {
+                    true => "distance_shadow_fragment",
+                    false => "shadow_fragment",
+                },
+                targets: &[],
+            }),
+            multiview: None,
+        })
+}
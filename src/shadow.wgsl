@@ -0,0 +1,69 @@
+struct Transform {
+    @location(2) data0: vec4<f32>,
+    @location(3) data1: vec4<f32>,
+    @location(4) data2: vec4<f32>,
+    @location(5) data3: vec4<f32>,
+};
+
+struct ShadowOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) alpha: f32,
+    @location(2) world_pos: vec3<f32>,
+};
+
+struct ShadowView {
+    view_proj: mat4x4<f32>,
+    // The position and range of point and spot lights
+    light: vec4<f32>,
+}
+
+@group(1)@binding(0)
+var<uniform> view: ShadowView;
+
+@group(0)@binding(0)
+var color_texture: texture_2d<f32>;
+@group(0)@binding(1)
+var color_texture_sampler: sampler;
+
+@vertex
+fn shadow_vertex(input: VertexInput, transform: Transform) -> ShadowOutput {
+    let in = vertex_input(input);
+    let transform_matrix = mat4x4<f32>(
+        transform.data0,
+        transform.data1,
+        transform.data2,
+        transform.data3,
+    );
+
+    var output: ShadowOutput;
+    let world_pos = transform_matrix * vec4<f32>(in.pos, 1.0);
+    output.pos = view.view_proj * world_pos;
+    output.tex_coords = material_uv(in);
+    output.alpha = in.color.a;
+    output.world_pos = world_pos.xyz;
+    return output;
+}
+
+// Leaves holes in the shadows of cut out materials
+fn alpha_test(in: ShadowOutput) {
+    if material.alpha_mode == 1u {
+        let alpha = textureSample(color_texture, color_texture_sampler, in.tex_coords).a * in.alpha;
+        if alpha < material.alpha_cutoff {
+            discard;
+        }
+    }
+}
+
+@fragment
+fn shadow_fragment(in: ShadowOutput) {
+    alpha_test(in);
+}
+
+// Point and spot lights store the distance to the light instead of the depth,
+// which is even at every distance and can be compared in any direction
+@fragment
+fn distance_shadow_fragment(in: ShadowOutput) -> @builtin(frag_depth) f32 {
+    alpha_test(in);
+    return distance(in.world_pos, view.light.xyz) / view.light.w;
+}
@@ -0,0 +1,56 @@
+use std::{fs::File, io, path::Path, sync::Arc};
+
+use rhachis::{renderers::Transform, GameData};
+
+use crate::{
+    material::Material,
+    model::{Model, NormalGeneration, TextureVertex},
+    Renderer,
+};
+
+impl Renderer {
+    /// Loads an ASCII or binary STL file as a single model, returning its id.
+    /// The normals stored in the file are ignored and recalculated from the
+    /// facets, since many exporters leave them empty.
+    pub fn load_stl<P: AsRef<Path>>(
+        &mut self,
+        data: &GameData,
+        path: P,
+        normals: NormalGeneration,
+        material: Arc<Material>,
+    ) -> io::Result<usize> {
+        let mesh = stl_io::read_stl(&mut File::open(path)?)?;
+
+        let mut vertices = mesh
+            .vertices
+            .iter()
+            .map(|vertex| TextureVertex {
+                pos: vertex.0,
+                ..Default::default()
+            })
+            .collect();
+        let mut indices: Vec<u32> = mesh
+            .faces
+            .iter()
+            .flat_map(|face| face.vertices.map(|index| index as u32))
+            .collect();
+        if indices
+            .iter()
+            .any(|&index| index as usize >= mesh.vertices.len())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid STL file: vertex index out of range",
+            ));
+        }
+        normals.apply(&mut vertices, &mut indices);
+
+        Ok(self.models.push(Model::new(
+            data,
+            vertices,
+            indices,
+            material,
+            vec![Transform::default()],
+        )))
+    }
+}
@@ -0,0 +1,350 @@
+use glam::UVec2;
+use rhachis::GameData;
+use wgpu::{BindGroup, Buffer, ComputePipeline, RenderPass, RenderPipeline, TextureView};
+
+/// The format models are drawn in before they're tonemapped, which can go
+/// above 1.
+pub(crate) const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const HISTOGRAM_BINS: u64 = 256;
+/// The size of the workgroups that build the histogram.
+const TILE: u32 = 16;
+
+/// How colors above 1 are brought into the range of the screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tonemapper {
+    /// Colors are clamped, so bright areas lose their detail.
+    Clamp,
+    Reinhard,
+    /// Stephen Hill's fit of the ACES filmic curve.
+    #[default]
+    Aces,
+    /// AgX, which desaturates bright colors more naturally than ACES.
+    AgX,
+}
+
+impl Tonemapper {
+    fn id(self) -> u32 {
+        match self {
+            Self::Clamp => 0,
+            Self::Reinhard => 1,
+            Self::Aces => 2,
+            Self::AgX => 3,
+        }
+    }
+}
+
+/// How bright the image is made before it's tonemapped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Exposure {
+    /// Scales colors by 2 to the power of the contained number of stops.
+    Manual(f32),
+    /// Measures the average brightness of the screen with a histogram and
+    /// adjusts to make it middle gray.
+    Auto {
+        /// Stops added on top of the measured exposure.
+        compensation: f32,
+        /// The darkest and brightest log2 luminance that is measured.
+        min_log_luminance: f32,
+        max_log_luminance: f32,
+        /// How quickly the exposure adapts to changes, higher is faster.
+        speed: f32,
+    },
+}
+
+impl Exposure {
+    /// Automatic exposure with reasonable defaults.
+    pub fn auto() -> Self {
+        Self::Auto {
+            compensation: 0.0,
+            min_log_luminance: -8.0,
+            max_log_luminance: 4.0,
+            speed: 1.5,
+        }
+    }
+}
+
+impl Default for Exposure {
+    fn default() -> Self {
+        Self::Manual(0.0)
+    }
+}
+
+/// The target models are drawn into, and the passes that measure its exposure
+/// and tonemap it onto the screen.
+pub(crate) struct Hdr {
+    pub view: TextureView,
+    size: UVec2,
+    settings: Buffer,
+    histogram: Buffer,
+    exposure: Buffer,
+    auto_exposure: bool,
+    /// Whether the shader has to encode colors as sRGB itself, because the
+    /// surface doesn't.
+    encode_srgb: bool,
+    bind_group: BindGroup,
+    histogram_pipeline: ComputePipeline,
+    average_pipeline: ComputePipeline,
+    tonemap_pipeline: RenderPipeline,
+}
+
+impl Hdr {
+    pub fn new(data: &GameData, size: UVec2) -> Self {
+        let device = &data.graphics.device;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("tonemap.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("tonemap.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&Self::bind_group_layout(data)],
+            push_constant_ranges: &[],
+        });
+
+        let compute = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+        let histogram_pipeline = compute("histogram_main");
+        let average_pipeline = compute("average_main");
+        let tonemap_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("tonemap_pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "tonemap_vertex",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "tonemap_fragment",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: data.graphics.config.format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        let buffer = |label, size, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        let settings = buffer(
+            "tonemap_settings",
+            std::mem::size_of::<TonemapSettings>() as u64,
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+        let histogram = buffer("histogram", HISTOGRAM_BINS * 4, wgpu::BufferUsages::STORAGE);
+        // Starts at 0 so the first measurement is used straight away
+        let exposure = buffer("exposure", 4, wgpu::BufferUsages::STORAGE);
+
+        let view = Self::target(data, size);
+        let bind_group = Self::bind_group(data, &settings, &view, &histogram, &exposure);
+        Self {
+            view,
+            size,
+            settings,
+            histogram,
+            exposure,
+            auto_exposure: false,
+            encode_srgb: !data.graphics.config.format.describe().srgb,
+            bind_group,
+            histogram_pipeline,
+            average_pipeline,
+            tonemap_pipeline,
+        }
+    }
+
+    pub fn resize(&mut self, data: &GameData, size: UVec2) {
+        self.size = size;
+        self.view = Self::target(data, size);
+        self.bind_group = Self::bind_group(
+            data,
+            &self.settings,
+            &self.view,
+            &self.histogram,
+            &self.exposure,
+        );
+    }
+
+    pub fn update(&mut self, data: &GameData, tonemapper: Tonemapper, exposure: Exposure) {
+        let settings = match exposure {
+            Exposure::Manual(stops) => TonemapSettings {
+                exposure: stops,
+                tonemapper: tonemapper.id(),
+                ..Default::default()
+            },
+            Exposure::Auto {
+                compensation,
+                min_log_luminance,
+                max_log_luminance,
+                speed,
+            } => TonemapSettings {
+                exposure: compensation,
+                auto_exposure: 1,
+                tonemapper: tonemapper.id(),
+                adaptation: 1.0 - (-data.delta_time.as_secs_f32() * speed).exp(),
+                min_log_luminance,
+                log_luminance_range: (max_log_luminance - min_log_luminance).max(0.0001),
+                ..Default::default()
+            },
+        };
+        let settings = TonemapSettings {
+            encode_srgb: self.encode_srgb as u32,
+            ..settings
+        };
+        self.auto_exposure = settings.auto_exposure == 1;
+        data.graphics
+            .queue
+            .write_buffer(&self.settings, 0, bytemuck::bytes_of(&settings));
+    }
+
+    /// Measures the average luminance of the target, if the exposure is
+    /// automatic.
+    pub fn measure(&self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.auto_exposure {
+            return;
+        }
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("exposure_pass"),
+        });
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.set_pipeline(&self.histogram_pipeline);
+        compute_pass.dispatch_workgroups(
+            self.size.x.max(1).div_ceil(TILE),
+            self.size.y.max(1).div_ceil(TILE),
+            1,
+        );
+        compute_pass.set_pipeline(&self.average_pipeline);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+    }
+
+    /// Draws the tonemapped target onto the screen.
+    pub fn resolve<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_pipeline(&self.tonemap_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn target(data: &GameData, size: UVec2) -> TextureView {
+        data.graphics
+            .device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("hdr_target"),
+                size: wgpu::Extent3d {
+                    width: size.x.max(1),
+                    height: size.y.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: HDR_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    fn bind_group(
+        data: &GameData,
+        settings: &Buffer,
+        view: &TextureView,
+        histogram: &Buffer,
+        exposure: &Buffer,
+    ) -> BindGroup {
+        data.graphics
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &Self::bind_group_layout(data),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: settings.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: histogram.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: exposure.as_entire_binding(),
+                    },
+                ],
+            })
+    }
+
+    fn bind_group_layout(data: &GameData) -> wgpu::BindGroupLayout {
+        let visibility = wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT;
+        let storage = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        data.graphics
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    storage(2),
+                    storage(3),
+                ],
+            })
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct TonemapSettings {
+    exposure: f32,
+    auto_exposure: u32,
+    tonemapper: u32,
+    adaptation: f32,
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    encode_srgb: u32,
+    _padding: u32,
+}
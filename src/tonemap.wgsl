@@ -0,0 +1,180 @@
+struct TonemapSettings {
+    // The exposure in stops, added to the measured one when it's automatic
+    exposure: f32,
+    // 1 when the exposure is measured from the histogram
+    auto_exposure: u32,
+    tonemapper: u32,
+    // How much of the way to the measured exposure to move this frame
+    adaptation: f32,
+    // The log2 luminance of the darkest bin and the range of all the bins
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    // 1 when the surface isn't sRGB, so the encoding is done here
+    encode_srgb: u32,
+    _padding: u32,
+}
+
+@group(0)@binding(0)
+var<uniform> settings: TonemapSettings;
+@group(0)@binding(1)
+var hdr_texture: texture_2d<f32>;
+
+struct Histogram {
+    bins: array<atomic<u32>, 256>,
+}
+
+struct Exposure {
+    // The average luminance, adapted over time
+    luminance: f32,
+}
+
+@group(0)@binding(2)
+var<storage, read_write> histogram: Histogram;
+@group(0)@binding(3)
+var<storage, read_write> exposure: Exposure;
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+var<workgroup> local_bins: array<atomic<u32>, 256>;
+
+// Counts the pixels of the screen by their log luminance, with the first bin
+// for black
+@compute @workgroup_size(16, 16)
+fn histogram_main(@builtin(global_invocation_id) id: vec3<u32>, @builtin(local_invocation_index) index: u32) {
+    atomicStore(&local_bins[index], 0u);
+    workgroupBarrier();
+
+    let size = vec2<u32>(textureDimensions(hdr_texture));
+    if all(id.xy < size) {
+        let lum = luminance(textureLoad(hdr_texture, vec2<i32>(id.xy), 0).rgb);
+        var bin = 0u;
+        if lum > 0.0001 {
+            let log_lum = clamp((log2(lum) - settings.min_log_luminance) / settings.log_luminance_range, 0.0, 1.0);
+            bin = u32(log_lum * 254.0 + 1.0);
+        }
+        atomicAdd(&local_bins[bin], 1u);
+    }
+
+    workgroupBarrier();
+    atomicAdd(&histogram.bins[index], atomicLoad(&local_bins[index]));
+}
+
+var<workgroup> weighted: array<f32, 256>;
+
+// Averages the histogram and moves the exposure towards it, clearing the
+// histogram for the next frame
+@compute @workgroup_size(256)
+fn average_main(@builtin(local_invocation_index) index: u32) {
+    let count = atomicExchange(&histogram.bins[index], 0u);
+    weighted[index] = f32(count) * f32(index);
+    workgroupBarrier();
+
+    for (var stride = 128u; stride > 0u; stride >>= 1u) {
+        if index < stride {
+            weighted[index] += weighted[index + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if index == 0u {
+        let size = vec2<u32>(textureDimensions(hdr_texture));
+        // Black pixels are left out so dark areas don't make the rest too
+        // bright
+        let lit = max(f32(size.x * size.y) - f32(count), 1.0);
+        let average_bin = max(weighted[0] / lit - 1.0, 0.0);
+        let log_lum = average_bin / 254.0 * settings.log_luminance_range + settings.min_log_luminance;
+        let target_lum = exp2(log_lum);
+        if exposure.luminance <= 0.0 {
+            exposure.luminance = target_lum;
+        } else {
+            exposure.luminance += (target_lum - exposure.luminance) * settings.adaptation;
+        }
+    }
+}
+
+// Covers the screen with a single triangle
+@vertex
+fn tonemap_vertex(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + color);
+}
+
+fn rrt_and_odt_fit(v: vec3<f32>) -> vec3<f32> {
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return a / b;
+}
+
+// The ACES fit by Stephen Hill, multiplying a row vector by these matrices
+// uses their rows as the columns
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let input = mat3x3<f32>(
+        vec3<f32>(0.59719, 0.35458, 0.04823),
+        vec3<f32>(0.07600, 0.90834, 0.01566),
+        vec3<f32>(0.02840, 0.13383, 0.83777),
+    );
+    let output = mat3x3<f32>(
+        vec3<f32>(1.60475, -0.53108, -0.07367),
+        vec3<f32>(-0.10208, 1.10813, -0.00605),
+        vec3<f32>(-0.00327, -0.07276, 1.07602),
+    );
+    return clamp(rrt_and_odt_fit(color * input) * output, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// The minimal AgX by Benjamin Wrensch, with a polynomial fit of the default
+// contrast curve
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let outset = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    let log_color = clamp(log2(max(inset * color, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    let x = (log_color - min_ev) / (max_ev - min_ev);
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    let curve = 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+    // The curve gives display values, which are turned back into linear ones
+    return pow(max(outset * curve, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn tonemap_fragment(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    var color = textureLoad(hdr_texture, vec2<i32>(pos.xy), 0).rgb * exp2(settings.exposure);
+    if settings.auto_exposure == 1u {
+        // Maps the average luminance to middle gray
+        color *= 0.18 / max(exposure.luminance, 0.0001);
+    }
+
+    switch settings.tonemapper {
+        case 1u: { color = reinhard(color); }
+        case 2u: { color = aces(color); }
+        case 3u: { color = agx(color); }
+        default: { color = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)); }
+    }
+    // Lighting is linear, which sRGB surfaces encode when they're written to
+    if settings.encode_srgb == 1u {
+        color = linear_to_srgb(color);
+    }
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,216 @@
+use std::fmt::Write;
+
+use glam::Vec3;
+
+use crate::model::TextureVertex;
+
+/// Something a vertex can store. Attributes are always laid out in the order
+/// of [`VertexAttribute::ALL`], with no padding between them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VertexAttribute {
+    Position,
+    TexCoord0,
+    Normal,
+    Color,
+    TexCoord1,
+    /// The tangent with the handedness of the bitangent in `w`.
+    Tangent,
+    /// Indices of the four joints of a skin affecting the vertex, as `u16`s.
+    Joints,
+    /// How much each of the joints affects the vertex.
+    Weights,
+}
+
+impl VertexAttribute {
+    pub const ALL: [Self; 8] = [
+        Self::Position,
+        Self::TexCoord0,
+        Self::Normal,
+        Self::Color,
+        Self::TexCoord1,
+        Self::Tangent,
+        Self::Joints,
+        Self::Weights,
+    ];
+
+    pub fn format(self) -> wgpu::VertexFormat {
+        match self {
+            Self::Position | Self::Normal => wgpu::VertexFormat::Float32x3,
+            Self::TexCoord0 | Self::TexCoord1 => wgpu::VertexFormat::Float32x2,
+            Self::Color | Self::Tangent | Self::Weights => wgpu::VertexFormat::Float32x4,
+            Self::Joints => wgpu::VertexFormat::Uint16x4,
+        }
+    }
+
+    /// Locations 2 to 5 are used by the instance transforms.
+    fn location(self) -> u32 {
+        match self {
+            Self::Position => 0,
+            Self::TexCoord0 => 1,
+            Self::Normal => 6,
+            Self::Color => 7,
+            Self::TexCoord1 => 8,
+            Self::Tangent => 9,
+            Self::Joints => 10,
+            Self::Weights => 11,
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << Self::ALL.iter().position(|x| *x == self).unwrap()
+    }
+
+    /// The name, type and value used when the attribute is missing, in WGSL.
+    fn wgsl(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Self::Position => ("pos", "vec3<f32>", "vec3<f32>(0.0)"),
+            Self::TexCoord0 => ("tex_coords", "vec2<f32>", "vec2<f32>(0.0)"),
+            Self::Normal => ("normal", "vec3<f32>", "vec3<f32>(0.0, 0.0, 1.0)"),
+            Self::Color => ("color", "vec4<f32>", "vec4<f32>(1.0)"),
+            Self::TexCoord1 => ("tex_coords_1", "vec2<f32>", "vec2<f32>(0.0)"),
+            Self::Tangent => ("tangent", "vec4<f32>", "vec4<f32>(1.0, 0.0, 0.0, 1.0)"),
+            Self::Joints => ("joints", "vec4<u32>", "vec4<u32>(0u)"),
+            Self::Weights => ("weights", "vec4<f32>", "vec4<f32>(1.0, 0.0, 0.0, 0.0)"),
+        }
+    }
+}
+
+/// The attributes stored in the vertex buffer of a [`Model`](crate::model::Model).
+/// Each layout gets its own variant of every pipeline, made the first time a
+/// model with it is rendered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VertexLayout(u8);
+
+impl VertexLayout {
+    /// The layout of [`TextureVertex`].
+    pub const TEXTURE_VERTEX: Self = Self(0b11111);
+
+    /// Makes a layout with the attributes, the position is always included.
+    pub fn new(attributes: &[VertexAttribute]) -> Self {
+        attributes.iter().fold(
+            Self(VertexAttribute::Position.bit()),
+            |layout, attribute| layout.with(*attribute),
+        )
+    }
+
+    pub fn with(self, attribute: VertexAttribute) -> Self {
+        Self(self.0 | attribute.bit())
+    }
+
+    pub fn contains(self, attribute: VertexAttribute) -> bool {
+        self.0 & attribute.bit() != 0
+    }
+
+    pub fn attributes(self) -> impl Iterator<Item = VertexAttribute> {
+        VertexAttribute::ALL
+            .into_iter()
+            .filter(move |attribute| self.contains(*attribute))
+    }
+
+    /// Where the attribute starts in each vertex, if the layout has it.
+    pub fn offset(self, attribute: VertexAttribute) -> Option<u64> {
+        self.contains(attribute).then(|| {
+            self.attributes()
+                .take_while(|x| *x != attribute)
+                .map(|x| x.format().size())
+                .sum()
+        })
+    }
+
+    /// The size of each vertex in bytes.
+    pub fn stride(self) -> u64 {
+        self.attributes().map(|x| x.format().size()).sum()
+    }
+
+    pub(crate) fn wgpu_attributes(self) -> Vec<wgpu::VertexAttribute> {
+        self.attributes()
+            .map(|attribute| wgpu::VertexAttribute {
+                format: attribute.format(),
+                offset: self.offset(attribute).unwrap(),
+                shader_location: attribute.location(),
+            })
+            .collect()
+    }
+
+    /// WGSL declaring a `VertexInput` struct with the attributes of the layout,
+    /// and a `vertex_input` function turning it into a `Vertex` with every
+    /// attribute, using defaults for the missing ones. This is put before the
+    /// built in shaders so they work with any layout.
+    pub fn shader_header(self) -> String {
+        let mut header = String::from("struct VertexInput {\n");
+        for attribute in self.attributes() {
+            let (name, ty, _) = attribute.wgsl();
+            writeln!(
+                header,
+                "    @location({}) {name}: {ty},",
+                attribute.location()
+            )
+            .unwrap();
+        }
+
+        header.push_str("};\n\nstruct Vertex {\n");
+        for attribute in VertexAttribute::ALL {
+            let (name, ty, _) = attribute.wgsl();
+            writeln!(header, "    {name}: {ty},").unwrap();
+        }
+
+        header.push_str("};\n\nfn vertex_input(in: VertexInput) -> Vertex {\n");
+        header.push_str("    var vertex: Vertex;\n");
+        for attribute in VertexAttribute::ALL {
+            let (name, _, default) = attribute.wgsl();
+            match self.contains(attribute) {
+                true => writeln!(header, "    vertex.{name} = in.{name};"),
+                false => writeln!(header, "    vertex.{name} = {default};"),
+            }
+            .unwrap();
+        }
+        header.push_str("    return vertex;\n}\n\n");
+
+        header
+    }
+
+    /// Reads the position of each vertex.
+    pub(crate) fn positions(self, bytes: &[u8]) -> impl Iterator<Item = Vec3> + '_ {
+        bytes
+            .chunks_exact(self.stride() as usize)
+            .map(|vertex| Vec3::from(read::<3>(vertex, 0)))
+    }
+
+    /// Converts vertices with this layout into [`TextureVertex`]es, dropping
+    /// attributes it doesn't have.
+    pub(crate) fn texture_vertices(self, bytes: &[u8]) -> Vec<TextureVertex> {
+        let default = TextureVertex::default();
+        let offset = |attribute| self.offset(attribute).map(|offset| offset as usize);
+        let [tex_coords, normals, color, tex_coords_1] = [
+            VertexAttribute::TexCoord0,
+            VertexAttribute::Normal,
+            VertexAttribute::Color,
+            VertexAttribute::TexCoord1,
+        ]
+        .map(offset);
+
+        bytes
+            .chunks_exact(self.stride() as usize)
+            .map(|vertex| TextureVertex {
+                pos: read(vertex, 0),
+                tex_coords: tex_coords.map_or(default.tex_coords, |x| read(vertex, x)),
+                normals: normals.map_or(default.normals, |x| read(vertex, x)),
+                color: color.map_or(default.color, |x| read(vertex, x)),
+                tex_coords_1: tex_coords_1.map_or(default.tex_coords_1, |x| read(vertex, x)),
+            })
+            .collect()
+    }
+}
+
+impl Default for VertexLayout {
+    fn default() -> Self {
+        Self::TEXTURE_VERTEX
+    }
+}
+
+fn read<const N: usize>(vertex: &[u8], offset: usize) -> [f32; N] {
+    std::array::from_fn(|i| {
+        let start = offset + i * 4;
+        bytemuck::pod_read_unaligned(&vertex[start..start + 4])
+    })
+}